@@ -0,0 +1,34 @@
+/* C ABI of the kindle-pult conversion core, see src/ffi.rs.
+ *
+ * Strings are UTF-8 and NUL-terminated. Strings and bytes returned are owned by the caller and
+ * freed with the matching kindle_pult_free_* function. On failure the functions return NULL
+ * and kindle_pult_last_error() tells why.
+ */
+#ifndef KINDLE_PULT_H
+#define KINDLE_PULT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Convert the web page at url with the saved settings and deliver it, returning the book path */
+char *kindle_pult_convert_url(const char *url);
+
+/* EPUB of a ReadabiliPy JSON result with the theme stylesheet ("serif", "sans" or "none"),
+ * image sources left as they are. Its size is written to len. */
+uint8_t *kindle_pult_epub_from_json(const char *json, const char *theme, size_t *len);
+
+/* Why the last call on this thread failed, NULL if none did. Valid until the next failure. */
+const char *kindle_pult_last_error(void);
+
+void kindle_pult_free_string(char *s);
+void kindle_pult_free_bytes(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,138 @@
+### Kindle-pult interface and generated pages
+
+## Main window
+convert = Convert
+history = History
+choose-a-file = Choose a file
+open = Open
+cancel = Cancel
+pause-queue = Pause queue
+url-label = URL:
+download = Download
+default-theme = Default theme
+default-format = Default format
+default-device = Default device
+send = Send
+
+## Article preview
+article-preview = Article preview
+title-label = Title:
+author-label = Author:
+tags-label = Tags:
+filename-label = Filename:
+deliver-to-label = Deliver to:
+preview = Preview
+images-detected =
+    { $count ->
+        [one] { $count } image detected, untick it to leave it out
+       *[other] { $count } images detected, untick the ones to leave out
+    }
+
+## Queue
+waiting = Waiting
+cancelled = Cancelled
+fetching-page = Fetching page
+extracting-article = Extracting article
+downloading-images = Downloading images { $done }/{ $total }
+building-epub = Building EPUB
+delivering-book = Delivering book
+done = Done
+failed = Failed
+
+## History
+send-to-device = Send to device
+convert-again = Convert again with current settings
+copy-source-url = Copy source URL
+
+## Clipboard
+send-to-kindle = Send to Kindle
+copied = Copied
+
+## Settings
+settings = Settings
+save = Save
+from-label = From:
+to-label = To:
+protocol-label = Protocol:
+port-label = Port:
+user-label = User:
+password-label = Password:
+extension-label = Extension:
+delete-sents = Delete sents
+output-folder-label = Output folder:
+output-folder = Output folder
+device-label = Device:
+parser-label = Parser:
+theme-label = Theme:
+format-label = Format:
+e-reader-folder-label = E-reader folder:
+e-reader-folder = E-reader folder
+cloud-folder-label = Cloud folder:
+cloud-user-label = Cloud user:
+cloud-password-label = Cloud password:
+watch-clipboard = Watch clipboard
+settings-unreadable = The settings file can't be read, so nothing was saved over it. Fix or remove it and try again.
+
+## Tray
+nothing-yet = Nothing yet
+show-window = Show window
+convert-clipboard-url = Convert clipboard URL
+recent-conversions = Recent conversions
+quit = Quit
+
+## Shortcuts
+cancel-current-conversion = Cancel current conversion
+focus-queue = Focus queue
+command-palette = Command palette
+
+## Errors
+error-download = The page or one of its images could not be downloaded. Check your connection and the URL.
+error-forbidden = The site refused access. It may need you to be logged in or accept cookies: save the page from your browser and drop the file here, or try its copy on the Wayback Machine (web.archive.org).
+error-not-found = The page does not exist. Check the URL, or look for an archived copy on the Wayback Machine (web.archive.org).
+error-server = The site is having problems. Try again later, or use its copy on the Wayback Machine (web.archive.org).
+error-http = The site answered with an error instead of the page. Check the URL.
+error-io = A file could not be read or written. Check the output folder and the free disk space.
+error-extract = No article could be extracted from the page. Check that ReadabiliPy is installed, or try the other parser.
+error-build = The book could not be assembled from the extracted article.
+error-image = One of the images could not be processed. Converting without images usually works.
+error-crashed = The conversion stopped unexpectedly.
+error-invalid-url = The URL is not valid.
+conversion-failed = Conversion failed
+stage = Stage
+error = Error
+retry = Retry
+retry-without-images = Retry without images
+copy-diagnostics = Copy diagnostics
+close = Close
+
+## Setup wizard
+set-up-kindle-pult = Set up Kindle-pult
+wizard-welcome = Kindle-pult turns web articles into e-books and sends them to your e-reader. A few questions and you are ready to go; everything can be changed later in the settings.
+welcome = Welcome
+wizard-device-question = Which e-reader do you use? Images are resized to fit its screen.
+device = Device
+kindle-address-label = Kindle address:
+wizard-email-help = Books are mailed to the Send-to-Kindle address of your device, shown in the device settings on Amazon. Amazon only accepts mails from approved senders: add the From address to the Approved Personal Document E-mail List (Manage Your Content and Devices > Preferences > Personal Document Settings), or the books never arrive.
+email = Email
+wizard-folder-question = Where should converted books be saved?
+wizard-sample = Convert a sample article to test the setup
+wizard-all-set = All set. Settings are saved when you press Apply.
+
+## Advanced options
+advanced = Advanced
+strip-images = Strip images
+turn-links-into-endnotes = Turn links into endnotes
+custom-css = Custom CSS
+custom-css-label = Custom CSS:
+as-found-in-the-page = As found in the page
+date-label = Date:
+description-label = Description:
+language-label = Language:
+
+## Generated pages
+table-of-contents = Table of contents
+notice-cut = The article was cut here because it is too long.
+notice-too-many-images = Some images were left out because the article has too many.
+notice-images-too-big = Some images were left out because they are too big altogether.
+source = Source
+notes = Notes
@@ -0,0 +1,138 @@
+### Kindle-pult interface and generated pages
+
+## Main window
+convert = Converti
+history = Cronologia
+choose-a-file = Scegli un file
+open = Apri
+cancel = Annulla
+pause-queue = Metti in pausa la coda
+url-label = URL:
+download = Scarica
+default-theme = Tema predefinito
+default-format = Formato predefinito
+default-device = Dispositivo predefinito
+send = Invia
+
+## Article preview
+article-preview = Anteprima articolo
+title-label = Titolo:
+author-label = Autore:
+tags-label = Etichette:
+filename-label = Nome file:
+deliver-to-label = Consegna a:
+preview = Anteprima
+images-detected =
+    { $count ->
+        [one] { $count } immagine trovata, deselezionala per escluderla
+       *[other] { $count } immagini trovate, deseleziona quelle da escludere
+    }
+
+## Queue
+waiting = In attesa
+cancelled = Annullato
+fetching-page = Scaricamento pagina
+extracting-article = Estrazione articolo
+downloading-images = Scaricamento immagini { $done }/{ $total }
+building-epub = Creazione EPUB
+delivering-book = Consegna libro
+done = Fatto
+failed = Fallito
+
+## History
+send-to-device = Invia al dispositivo
+convert-again = Converti di nuovo con le impostazioni attuali
+copy-source-url = Copia URL di origine
+
+## Clipboard
+send-to-kindle = Invia a Kindle
+copied = Copiato
+
+## Settings
+settings = Impostazioni
+save = Salva
+from-label = Da:
+to-label = A:
+protocol-label = Protocollo:
+port-label = Porta:
+user-label = Utente:
+password-label = Password:
+extension-label = Estensione:
+delete-sents = Elimina inviati
+output-folder-label = Cartella di destinazione:
+output-folder = Cartella di destinazione
+device-label = Dispositivo:
+parser-label = Parser:
+theme-label = Tema:
+format-label = Formato:
+e-reader-folder-label = Cartella dell'e-reader:
+e-reader-folder = Cartella dell'e-reader
+cloud-folder-label = Cartella cloud:
+cloud-user-label = Utente cloud:
+cloud-password-label = Password cloud:
+watch-clipboard = Controlla gli appunti
+settings-unreadable = Il file delle impostazioni non è leggibile, quindi non è stato sovrascritto. Correggilo o eliminalo e riprova.
+
+## Tray
+nothing-yet = Ancora niente
+show-window = Mostra finestra
+convert-clipboard-url = Converti URL negli appunti
+recent-conversions = Conversioni recenti
+quit = Esci
+
+## Shortcuts
+cancel-current-conversion = Annulla la conversione in corso
+focus-queue = Vai alla coda
+command-palette = Palette dei comandi
+
+## Errors
+error-download = Non è stato possibile scaricare la pagina o una delle sue immagini. Controlla la connessione e l'URL.
+error-forbidden = Il sito ha rifiutato l'accesso. Potrebbe richiedere l'accesso o l'accettazione dei cookie: salva la pagina dal browser e trascina qui il file, o prova la sua copia sulla Wayback Machine (web.archive.org).
+error-not-found = La pagina non esiste. Controlla l'URL o cerca una copia archiviata sulla Wayback Machine (web.archive.org).
+error-server = Il sito ha dei problemi. Riprova più tardi o usa la sua copia sulla Wayback Machine (web.archive.org).
+error-http = Il sito ha risposto con un errore invece della pagina. Controlla l'URL.
+error-io = Non è stato possibile leggere o scrivere un file. Controlla la cartella di destinazione e lo spazio libero.
+error-extract = Non è stato possibile estrarre un articolo dalla pagina. Controlla che ReadabiliPy sia installato o prova l'altro parser.
+error-build = Non è stato possibile creare il libro dall'articolo estratto.
+error-image = Non è stato possibile elaborare una delle immagini. Di solito la conversione senza immagini funziona.
+error-crashed = La conversione si è interrotta inaspettatamente.
+error-invalid-url = L'URL non è valido.
+conversion-failed = Conversione fallita
+stage = Fase
+error = Errore
+retry = Riprova
+retry-without-images = Riprova senza immagini
+copy-diagnostics = Copia diagnostica
+close = Chiudi
+
+## Setup wizard
+set-up-kindle-pult = Configura Kindle-pult
+wizard-welcome = Kindle-pult trasforma gli articoli web in e-book e li invia al tuo e-reader. Bastano poche domande; tutto si può cambiare più tardi nelle impostazioni.
+welcome = Benvenuto
+wizard-device-question = Quale e-reader usi? Le immagini vengono ridimensionate per il suo schermo.
+device = Dispositivo
+kindle-address-label = Indirizzo Kindle:
+wizard-email-help = I libri vengono inviati all'indirizzo Send-to-Kindle del dispositivo, indicato nelle impostazioni del dispositivo su Amazon. Amazon accetta solo mail da mittenti approvati: aggiungi l'indirizzo Da all'elenco degli indirizzi e-mail approvati per i documenti personali (Gestisci contenuti e dispositivi > Preferenze > Impostazioni documenti personali), altrimenti i libri non arrivano.
+email = Email
+wizard-folder-question = Dove salvare i libri convertiti?
+wizard-sample = Converti un articolo di prova per verificare la configurazione
+wizard-all-set = Tutto pronto. Le impostazioni vengono salvate premendo Applica.
+
+## Advanced options
+advanced = Avanzate
+strip-images = Rimuovi immagini
+turn-links-into-endnotes = Trasforma i link in note finali
+custom-css = CSS personalizzato
+custom-css-label = CSS personalizzato:
+as-found-in-the-page = Come nella pagina
+date-label = Data:
+description-label = Descrizione:
+language-label = Lingua:
+
+## Generated pages
+table-of-contents = Indice
+notice-cut = L'articolo è stato tagliato qui perché è troppo lungo.
+notice-too-many-images = Alcune immagini sono state escluse perché l'articolo ne ha troppe.
+notice-images-too-big = Alcune immagini sono state escluse perché nel complesso sono troppo grandi.
+source = Fonte
+notes = Note
@@ -1,3 +1,4 @@
+use std::io;
 use std::process::Command;
 use std::collections::HashMap;
 
@@ -24,7 +25,25 @@ impl CalibreCmd {
         String::from_utf8_lossy(&output.stdout).to_string()
     }
 
-    pub fn send(filename: &str, cfg: HashMap<String, String>) -> String {
+    /// Convert `input` into `output`, formats follow the file extensions
+    pub fn convert_to(input: &str, output: &str) -> String {
+        let convert_arg = format!(r#"ebook-convert "{}" "{}""#, input, output);
+
+        println!("***** conversion *****");
+        let output = if cfg!(target_os = "windows") {
+            Command::new("cmd").arg("/C").arg(&convert_arg).output()
+            .expect("Windows failed to execute convert cmd")
+        } else {
+            Command::new("sh").arg("-c").arg(&convert_arg).output()
+            .expect("Linux failed to execute convert cmd")
+        };
+
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    /// Mail the converted `filename` with calibre-smtp, failing when it can't be run or
+    /// reports an error (SMTP refused, network down)
+    pub fn send(filename: &str, cfg: HashMap<String, String>) -> io::Result<String> {
         println!("***** sending... *****");
 
         // Calibre Send smtp commands
@@ -46,17 +65,19 @@ impl CalibreCmd {
 
         // Launch command
         let output = if cfg!(target_os = "windows") {
-            Command::new("cmd").arg("/C").arg(&this_smtp_arg).output()
-            .expect("Windows failed to execute send cmd")
+            Command::new("cmd").arg("/C").arg(&this_smtp_arg).output()?
         } else {
-            Command::new("sh").arg("-c").arg(&this_smtp_arg).output()
-            .expect("Linux failed to execute send cmd")
+            Command::new("sh").arg("-c").arg(&this_smtp_arg).output()?
         };
 
         // Shell output
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
         println!("{}", stdout);
-        stdout
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!("calibre-smtp failed ({}): {}", output.status, stderr.trim())))
+        }
+        Ok(stdout)
     }
 }  // CalibreCmd
 
@@ -69,6 +90,16 @@ pub struct ReadabiliPyCmd {
     parser: ReadabiliPyParser,
 }
 
+impl ReadabiliPyParser {
+    /// Parser from its config name, Mozilla's one by default
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "python" => ReadabiliPyParser::Python,
+            _ => ReadabiliPyParser::Mozilla,
+        }
+    }
+}
+
 impl ReadabiliPyCmd {
     pub fn new(parser: ReadabiliPyParser) -> Self {
         Self {
@@ -103,3 +134,40 @@ impl ReadabiliPyCmd {
         String::from_utf8_lossy(&output.stdout).to_string()
     }
 }
+
+pub struct HookCmd {}
+
+impl HookCmd {
+    /// Run user `hook` with `path` as its only argument; empty hooks are skipped.
+    pub fn run(hook: &str, path: &str) -> Option<String> {
+        if hook.trim().is_empty() {
+            return None
+        }
+
+        println!("***** hook: {} {:?} *****", hook, path);
+        let output = if cfg!(target_os = "windows") {
+            let hook_arg = format!(r#"{hook} "{path}""#, hook = hook, path = path);
+            Command::new("cmd").arg("/C").arg(&hook_arg).output()
+            .expect("Windows failed to execute hook cmd")
+        } else {
+            // The path goes in as $1, never through the shell parser: titles can hold quotes,
+            // `$(...)` and the like
+            Command::new("sh").args(["-c", &format!(r#"{} "$1""#, hook), "sh", path]).output()
+            .expect("Linux failed to execute hook cmd")
+        };
+
+        Some(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_get_the_path_verbatim() {
+        let path = r#"/tmp/"quoted" $(echo injected) `echo too`.epub"#;
+        assert_eq!(HookCmd::run("printf %s", path).as_deref(), Some(path));
+        assert_eq!(HookCmd::run("  ", path), None);
+    }
+}
@@ -1,10 +1,11 @@
 use serde::{Serialize, Deserialize};
+#[cfg(not(target_arch = "wasm32"))]
 use std::collections::HashMap;
-use std::result::Result;
 
 // Config file serialization
 // PultConf is for sending and converting
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]  // Missing fields in older config files fall back to defaults
 pub struct PultConf {
     pub del_sent: String,
     pub to_ext: String,
@@ -14,6 +15,59 @@ pub struct PultConf {
     pub password: String,
     pub from_mail: String,
     pub to_mail: String,
+    // Hooks are shell commands run at pipeline milestones, empty to disable
+    pub hook_extracted: String,  // Receives the article JSON path
+    pub hook_epub: String,  // Receives the generated EPUB path
+    pub hook_sent: String,  // Receives the sent file path
+    // Image transforms, images are only decoded when one of them applies
+    pub img_max_width: String,  // Downscale wider images, "0" to follow the device profile
+    pub img_greyscale: String,
+    pub img_min_size: String,  // Drop smaller images (tracking pixels, icons), in pixels, "0" to keep all
+    pub img_min_bytes: String,  // Drop smaller image files, "0" to keep all
+    // Caps for pathological pages, past them the article is cut with a notice, "0" for no cap
+    pub max_content_kb: String,
+    pub max_images: String,
+    pub max_resources_mb: String,  // Total size of the images
+    pub out_dir: String,  // Where EPUBs are saved, empty for `paths::default_out_dir`
+    pub device: String,  // One of `DEVICE_PROFILES`
+    pub parser: String,  // ReadabiliPy parser, "mozilla" or "python"
+    pub css_theme: String,  // One of `web::THEMES`
+    pub out_format: String,  // One of `OUT_FORMATS`, non EPUB books are converted with Calibre
+    pub delivery: String,  // Default `delivery::TARGETS` entry, remembered from the last pick
+    pub device_dir: String,  // Documents folder of the connected e-reader
+    pub cloud_url: String,  // WebDAV folder books are uploaded to
+    pub cloud_username: String,
+    pub cloud_password: String,
+    // Network
+    pub tls_ca_file: String,  // Extra PEM bundle of trusted CAs, empty for the system ones only
+    pub tls_insecure_hosts: String,  // Comma separated hosts whose certificates are not checked
+    pub dns_over_https: String,  // DNS-over-HTTPS JSON API URL, empty for the system resolver
+    pub dns_overrides: String,  // Comma separated host=address pairs
+    pub max_download_rate: String,  // KiB/s, "0" for no cap
+    pub report: String,  // Conversion report saved next to each book: "off", "text" or "json"
+    pub watch_clipboard: String,  // Offer to convert copied URLs
+    pub setup_done: String,  // The first-run wizard was completed or dismissed
+}
+
+/// Known e-readers and their screen width, used to downscale images
+pub const DEVICE_PROFILES: &[(&str, u32)] = &[
+    ("none", 0),
+    ("kindle", 600),
+    ("paperwhite", 1072),
+    ("oasis", 1264),
+    ("kobo", 1072),
+    ("tablet", 1600),
+];
+
+/// Formats books can be saved as
+pub const OUT_FORMATS: &[&str] = &["epub", "mobi", "azw3", "pdf"];
+
+/// Screen width of `device`, 0 if unknown
+pub fn device_width(device: &str) -> u32 {
+    DEVICE_PROFILES.iter()
+        .find(|(name, _)| *name == device)
+        .map(|(_, width)| *width)
+        .unwrap_or(0)
 }
 
 /// `PultConf` implements `Default`
@@ -28,11 +82,40 @@ impl Default for PultConf {
             password: "your-password".into(),
             from_mail: "user.name@gmail.com".into(),
             to_mail: "ebook-mail@kindle.com".into(),
+            hook_extracted: "".into(),
+            hook_epub: "".into(),
+            hook_sent: "".into(),
+            img_max_width: "0".into(),
+            img_greyscale: "false".into(),
+            img_min_size: "32".into(),
+            img_min_bytes: "128".into(),
+            max_content_kb: "2048".into(),
+            max_images: "200".into(),
+            max_resources_mb: "100".into(),
+            out_dir: "".into(),
+            device: "kindle".into(),
+            parser: "mozilla".into(),
+            css_theme: "serif".into(),
+            out_format: "epub".into(),
+            delivery: "folder".into(),
+            device_dir: "".into(),
+            cloud_url: "".into(),
+            cloud_username: "".into(),
+            cloud_password: "".into(),
+            tls_ca_file: "".into(),
+            tls_insecure_hosts: "".into(),
+            dns_over_https: "".into(),
+            dns_overrides: "".into(),
+            max_download_rate: "0".into(),
+            report: "off".into(),
+            watch_clipboard: "false".into(),
+            setup_done: "false".into(),
         }
     }
 }
 
 impl PultConf {
+    #[cfg(not(target_arch = "wasm32"))]
     fn dump_to_hashmap(&self) -> HashMap<String, String> {
         let mut values = HashMap::new();
         values.insert(String::from("del_sent"), String::from(&self.del_sent));
@@ -43,28 +126,48 @@ impl PultConf {
         values.insert(String::from("password"), String::from(&self.password));
         values.insert(String::from("from_mail"), String::from(&self.from_mail));
         values.insert(String::from("to_mail"), String::from(&self.to_mail));
+        values.insert(String::from("hook_extracted"), String::from(&self.hook_extracted));
+        values.insert(String::from("hook_epub"), String::from(&self.hook_epub));
+        values.insert(String::from("hook_sent"), String::from(&self.hook_sent));
+        values.insert(String::from("img_max_width"), String::from(&self.img_max_width));
+        values.insert(String::from("img_greyscale"), String::from(&self.img_greyscale));
+        values.insert(String::from("img_min_size"), String::from(&self.img_min_size));
+        values.insert(String::from("img_min_bytes"), String::from(&self.img_min_bytes));
+        values.insert(String::from("max_content_kb"), String::from(&self.max_content_kb));
+        values.insert(String::from("max_images"), String::from(&self.max_images));
+        values.insert(String::from("max_resources_mb"), String::from(&self.max_resources_mb));
+        values.insert(String::from("out_dir"), String::from(&self.out_dir));
+        values.insert(String::from("device"), String::from(&self.device));
+        values.insert(String::from("parser"), String::from(&self.parser));
+        values.insert(String::from("css_theme"), String::from(&self.css_theme));
+        values.insert(String::from("out_format"), String::from(&self.out_format));
+        values.insert(String::from("delivery"), String::from(&self.delivery));
+        values.insert(String::from("device_dir"), String::from(&self.device_dir));
+        values.insert(String::from("cloud_url"), String::from(&self.cloud_url));
+        values.insert(String::from("cloud_username"), String::from(&self.cloud_username));
+        values.insert(String::from("cloud_password"), String::from(&self.cloud_password));
+        values.insert(String::from("tls_ca_file"), String::from(&self.tls_ca_file));
+        values.insert(String::from("tls_insecure_hosts"), String::from(&self.tls_insecure_hosts));
+        values.insert(String::from("dns_over_https"), String::from(&self.dns_over_https));
+        values.insert(String::from("dns_overrides"), String::from(&self.dns_overrides));
+        values.insert(String::from("max_download_rate"), String::from(&self.max_download_rate));
+        values.insert(String::from("report"), String::from(&self.report));
+        values.insert(String::from("watch_clipboard"), String::from(&self.watch_clipboard));
+        values.insert(String::from("setup_done"), String::from(&self.setup_done));
 
         values
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn reload() -> HashMap<String, String> {
-        // Load config file info
-        let confy_loaded: Result<PultConf, confy::ConfyError> = confy::load("kindle-pult");
-
-        // Reset to default if some error occurs
-        let conf_hashmap: HashMap<String, String> = match confy_loaded {
-            Ok(c) => {
-                c.dump_to_hashmap()
-            },
+        // Defaults stand in for a file that can't be read, which is left as it is for the
+        // user to fix: storing over it would lose every setting in it
+        match confy::load::<PultConf>("kindle-pult") {
+            Ok(c) => c.dump_to_hashmap(),
             Err(e) => {
-                println!("{:?}", e);
-                println!("Replacing with default config values and dumping.");
-                let _ = confy::store("kindle-pult", PultConf::default());
-                let default_c: PultConf = confy::load("kindle-pult").unwrap();
-                default_c.dump_to_hashmap()
+                println!("Can't load settings, using the defaults: {}", e);
+                PultConf::default().dump_to_hashmap()
             },
-        };
-
-        conf_hashmap
+        }
     }
 }
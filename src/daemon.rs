@@ -0,0 +1,120 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::panic::{catch_unwind, AssertUnwindSafe};
+#[cfg(unix)]
+use std::sync::mpsc;
+#[cfg(unix)]
+use std::thread;
+
+#[cfg(unix)]
+use url::Url;
+
+#[cfg(unix)]
+use crate::config::PultConf;
+#[cfg(unix)]
+use crate::history::History;
+#[cfg(unix)]
+use crate::web::{Article, HttpMode, Progress};
+
+// Resident job server: owns the conversion queue and is controlled over a local socket
+// with one-line commands (`add <url>`), answered with one line.
+// Only the CLI talks to it: the GUI keeps its own queue, as it previews articles before
+// converting them. Windows has no transport yet.
+pub struct Daemon {}
+
+impl Daemon {
+    pub fn socket_path() -> io::Result<PathBuf> {
+        Ok(crate::paths::runtime_dir()?.join("kindle-pult.sock"))
+    }
+
+    #[cfg(unix)]
+    pub fn run(http_mode: HttpMode) -> io::Result<()> {
+        let path = Self::socket_path()?;
+        let _ = fs::remove_file(&path);  // Stale socket from a previous run
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;  // Only the user queues work
+        println!("kindle-pult daemon listening on {:?}", path);
+
+        // Worker converting queued URLs one at a time
+        let (queue, jobs) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for url in jobs {
+                println!("***** converting {} *****", url);
+                let progress = |p: Progress| println!("{}: {}", url, p.label());
+                // A crashing conversion must not take the worker, and the queue, down with it
+                let converted = catch_unwind(AssertUnwindSafe(|| {
+                    Article::epub_from_url(url.clone(), PultConf::reload(), http_mode.clone(), &progress)
+                }));
+                match converted {
+                    Ok(Ok(Some((title, path)))) => History::record(&url, &title, path),
+                    Ok(Ok(None)) => println!("Invalid URL: {}", url),
+                    Ok(Err(e)) => println!("errore: {} ({})", e, url),
+                    Err(_) => println!("Conversion of {} crashed", url),
+                }
+            }
+        });
+
+        // A misbehaving client only loses its own connection
+        let mut queued = 0;
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| Self::handle(stream, &queue, &mut queued));
+            if let Err(e) = result {
+                println!("Client connection failed: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Answer the one-line command of a client
+    #[cfg(unix)]
+    fn handle(mut stream: UnixStream, queue: &mpsc::Sender<String>, queued: &mut usize) -> io::Result<()> {
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+
+        let reply = match line.trim().split_once(' ') {
+            // Web pages only, local files are the user's to convert
+            Some(("add", url)) if !matches!(Url::parse(url), Ok(url) if url.scheme() == "http" || url.scheme() == "https") => {
+                format!("error: not a web page: {}", url)
+            },
+            Some(("add", url)) => match queue.send(url.to_string()) {
+                Ok(()) => {
+                    *queued += 1;
+                    format!("queued {} ({})", url, queued)
+                },
+                Err(_) => "error: the conversion worker stopped".to_string(),
+            },
+            _ => format!("unknown command: {}", line.trim()),
+        };
+        writeln!(stream, "{}", reply)
+    }
+
+    #[cfg(not(unix))]
+    pub fn run(_http_mode: crate::web::HttpMode) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "daemon mode is only available on Unix"))
+    }
+
+    /// Send a command to a running daemon and return its reply
+    #[cfg(unix)]
+    pub fn send(command: &str) -> io::Result<String> {
+        let mut stream = UnixStream::connect(Self::socket_path()?)?;
+        writeln!(stream, "{}", command)?;
+
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply)?;
+        Ok(reply.trim().to_string())
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(_command: &str) -> io::Result<String> {
+        Err(io::Error::new(io::ErrorKind::Other, "daemon mode is only available on Unix"))
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tempfile::NamedTempFile;
+use url::Url;
+
+use crate::cmd::{CalibreCmd, HookCmd};
+use crate::http::HttpClient;
+use crate::web::errors::*;
+
+/// Where converted books go: kept in the output folder, mailed to the Kindle address,
+/// copied to a connected e-reader or uploaded to a cloud folder
+pub const TARGETS: &[&str] = &["folder", "email", "device", "cloud"];
+
+/// Hidden temp file next to `dest`, with the same extension so tools can tell the format.
+/// Renamed over `dest` with `persist` once complete, removed if dropped before.
+pub fn partial_file(dest: &Path) -> Result<NamedTempFile> {
+    let dir = dest.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let name = dest.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let suffix = match dest.extension() {
+        Some(ext) => format!(".part.{}", ext.to_string_lossy()),
+        None => ".part".to_string(),
+    };
+
+    Ok(tempfile::Builder::new().prefix(&format!(".{}.", name)).suffix(&suffix).tempfile_in(dir)?)
+}
+
+/// Write `data` to `dest` in one go: readers and sync tools never see a truncated file
+pub fn write_atomic(dest: &Path, data: &[u8]) -> Result<()> {
+    let mut part = partial_file(dest)?;
+    part.write_all(data)?;
+    part.as_file().sync_all()?;
+    part.persist(dest).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Convert `file` to the configured format if needed and mail it to the device, then run
+/// the after-delivery hook. Fails when the file doesn't exist or the mail can't be sent.
+pub fn send_by_mail(file: &Path, cfg: &HashMap<String, String>) -> Result<()> {
+    mail(file, cfg)?;
+    sent(file, cfg);
+    delete_sent(file, cfg);
+
+    Ok(())
+}
+
+/// Run the after-delivery hook on the delivered `file`
+fn sent(file: &Path, cfg: &HashMap<String, String>) {
+    HookCmd::run(cfg.get("hook_sent").unwrap(), &file.to_string_lossy());
+}
+
+/// Mail `file` to the device, converting it first if needed
+fn mail(file: &Path, cfg: &HashMap<String, String>) -> Result<()> {
+    // Check file and its extension
+    if file.exists() {
+        // CD in file directory
+        let file_dir = file.parent().unwrap();
+        let _cd_success = env::set_current_dir(&file_dir);
+        let from_ext = file.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        let to_ext = cfg.get("to_ext").unwrap();
+
+        if from_ext == to_ext {
+            println!("Conversion unnecessary");
+        } else {
+            let _conv_output = CalibreCmd::convert(file.to_str().unwrap(), &to_ext);
+        }
+    } else {
+        bail!("Can't mail {:?}, file not found", file);
+    }
+
+    let filename = file.file_stem().unwrap();
+    CalibreCmd::send(filename.to_str().unwrap(), cfg.clone())?;
+
+    Ok(())
+}
+
+/// Delete the mailed `file` if sent files are not kept
+fn delete_sent(file: &Path, cfg: &HashMap<String, String>) {
+    if cfg.get("del_sent").unwrap().parse().unwrap_or(false) {
+        let _del_result = fs::remove_file(file);
+    }
+}
+
+/// Upload the file at `path` into the WebDAV folder set as `cloud_url` (Nextcloud, ownCloud,
+/// most NAS), returning the URL of the uploaded file
+fn upload(path: &Path, cfg: &HashMap<String, String>) -> Result<Url> {
+    let option = |key: &str| cfg.get(key).map(|value| value.trim()).unwrap_or_default();
+    if option("cloud_url").is_empty() {
+        bail!("No cloud folder set, add its WebDAV URL in the settings");
+    }
+    let mut dest = Url::parse(option("cloud_url"))
+        .map_err(|e| format!("Invalid cloud folder URL '{}': {}", option("cloud_url"), e))?;
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    dest.path_segments_mut()
+        .map_err(|_| format!("Invalid cloud folder URL '{}'", option("cloud_url")))?
+        .pop_if_empty()
+        .push(&name);
+
+    let response = HttpClient::from_cfg(cfg)?
+        .put(&dest, fs::read(path)?, option("cloud_username"), option("cloud_password"))?;
+    if !response.status().is_success() {
+        bail!("Upload to '{}' failed: HTTP {}", dest, response.status());
+    }
+
+    Ok(dest)
+}
+
+/// Deliver the book at `path` to `target`, one of `TARGETS`, returning where it ended up.
+/// The after-delivery hook runs once the book got there, whatever the target.
+pub fn deliver(path: &Path, target: &str, cfg: &HashMap<String, String>) -> Result<PathBuf> {
+    let delivered = match target {
+        "email" => {
+            mail(path, cfg)?;
+            path.to_path_buf()
+        },
+        "device" => {
+            let device_dir = Path::new(cfg.get("device_dir").unwrap());
+            if device_dir.as_os_str().is_empty() || !device_dir.is_dir() {
+                bail!("E-reader folder {:?} not found, is the device connected?", device_dir);
+            }
+            let dest = device_dir.join(path.file_name().unwrap());
+            write_atomic(&dest, &fs::read(path)?)?;
+            dest
+        },
+        "cloud" => {
+            let url = upload(path, cfg)?;
+            println!("Uploaded to {}", url);
+            path.to_path_buf()  // A copy stays in the output folder
+        },
+        _ => path.to_path_buf(),  // Already saved in the output folder
+    };
+    sent(&delivered, cfg);
+    if target == "email" {
+        delete_sent(path, cfg);
+    }
+
+    Ok(delivered)
+}
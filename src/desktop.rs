@@ -0,0 +1,37 @@
+use std::io;
+#[cfg(target_os = "linux")]
+use std::{fs, path::{Path, PathBuf}, process::Command};
+
+/// Desktop entry declaring the `kindle-pult://` scheme and the "Open with" types
+#[cfg(target_os = "linux")]
+const DESKTOP_ENTRY: &str = include_str!("../kindle-pult.desktop");
+#[cfg(target_os = "linux")]
+const DESKTOP_FILE: &str = "kindle-pult.desktop";
+
+/// Install the desktop entry for the current user and make Kindle-pult the handler of
+/// `kindle-pult://convert?url=...` links. HTML and mail files only get an "Open with" entry,
+/// their default applications are left alone.
+#[cfg(target_os = "linux")]
+pub fn register_handlers() -> io::Result<()> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    let apps_dir = data_dir.join("applications");
+    fs::create_dir_all(&apps_dir)?;
+
+    // Point the entry to this very executable, it may not be in PATH
+    let exe = std::env::current_exe()?;
+    let entry = DESKTOP_ENTRY.replace("Exec=kindle-pult", &format!("Exec=\"{}\"", exe.display()));
+    fs::write(apps_dir.join(DESKTOP_FILE), entry)?;
+
+    Command::new("xdg-mime").args(["default", DESKTOP_FILE, "x-scheme-handler/kindle-pult"]).status()?;
+    let _ = Command::new("update-desktop-database").arg(&apps_dir).status();  // Optional, refreshes "Open with"
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn register_handlers() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "handler registration is only available on Linux"))
+}
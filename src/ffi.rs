@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fmt::Display;
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::config::PultConf;
+use crate::web::{theme_css, Article, HttpMode};
+
+// C ABI of the conversion core, declared in `include/kindle_pult.h`.
+// Strings are UTF-8 and NUL-terminated. Strings and bytes returned to the caller are owned by
+// it and freed with the matching `kindle_pult_free_*` function; on failure the functions return
+// null and `kindle_pult_last_error` tells why.
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Remember `e` for `kindle_pult_last_error`
+fn set_error(e: impl Display) {
+    let message = CString::new(e.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// `s` as a string slice, `None` if it is null or not UTF-8
+///
+/// # Safety
+/// A non-null `s` must point to a NUL-terminated string that outlives the slice.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_error(format!("{} is null", name));
+        return None
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(e) => { set_error(format!("{} is not UTF-8: {}", name, e)); None },
+    }
+}
+
+/// Convert the web page at `url` with the saved settings and deliver it like the GUI would,
+/// returning the path of the book.
+///
+/// # Safety
+/// `url` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kindle_pult_convert_url(url: *const c_char) -> *mut c_char {
+    let url = match str_arg(url, "url") {
+        Some(url) => url,
+        None => return ptr::null_mut(),
+    };
+    match Article::epub_from_url(url.to_string(), PultConf::reload(), HttpMode::Live, &|_| {}) {
+        Ok(Some((_, path))) => CString::new(path.to_string_lossy().into_owned()).map(CString::into_raw).unwrap_or_else(|e| {
+            set_error(e);
+            ptr::null_mut()
+        }),
+        Ok(None) => { set_error(format!("not a valid URL: {}", url)); ptr::null_mut() },
+        Err(e) => { set_error(e); ptr::null_mut() },
+    }
+}
+
+/// EPUB of a ReadabiliPy JSON result with the `theme` stylesheet (one of `THEMES`), image
+/// sources left as they are. Its size is written to `len`.
+///
+/// # Safety
+/// `json` and `theme` must be NUL-terminated strings, `len` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn kindle_pult_epub_from_json(json: *const c_char, theme: *const c_char, len: *mut usize) -> *mut u8 {
+    let (json, theme) = match (str_arg(json, "json"), str_arg(theme, "theme")) {
+        (Some(json), Some(theme)) => (json, theme),
+        _ => return ptr::null_mut(),
+    };
+    if len.is_null() {
+        set_error("len is null");
+        return ptr::null_mut()
+    }
+    match Article::from_json(json).and_then(|article| article.build_epub(Vec::new(), theme_css(theme))) {
+        Ok(epub) => {
+            *len = epub.len();
+            Box::into_raw(epub.into_boxed_slice()) as *mut u8
+        },
+        Err(e) => { set_error(e); ptr::null_mut() },
+    }
+}
+
+/// Why the last call on this thread failed, null if none did. The message stays valid until
+/// the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn kindle_pult_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Free a string returned by kindle-pult
+///
+/// # Safety
+/// `s` must come from a kindle-pult function and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn kindle_pult_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Free the bytes returned by `kindle_pult_epub_from_json`, `len` being the size it wrote
+///
+/// # Safety
+/// `data` must come from `kindle_pult_epub_from_json` with that `len`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn kindle_pult_free_bytes(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+
+    #[test]
+    fn epub_from_json_round_trips_through_the_c_abi() {
+        let json = CString::new(r#"{"title": "T", "content": "<p>Hi</p>"}"#).unwrap();
+        let theme = CString::new("serif").unwrap();
+        let mut len = 0;
+        unsafe {
+            let epub = kindle_pult_epub_from_json(json.as_ptr(), theme.as_ptr(), &mut len);
+            assert!(!epub.is_null());
+            assert_eq!(slice::from_raw_parts(epub, 2), b"PK");
+            kindle_pult_free_bytes(epub, len);
+        }
+    }
+
+    #[test]
+    fn failures_leave_an_error_message() {
+        let json = CString::new("not json").unwrap();
+        let mut len = 0;
+        unsafe {
+            assert!(kindle_pult_epub_from_json(json.as_ptr(), ptr::null(), &mut len).is_null());
+            assert_eq!(CStr::from_ptr(kindle_pult_last_error()).to_str().unwrap(), "theme is null");
+            let theme = CString::new("serif").unwrap();
+            assert!(kindle_pult_epub_from_json(json.as_ptr(), theme.as_ptr(), &mut len).is_null());
+            assert!(!CStr::from_ptr(kindle_pult_last_error()).to_bytes().is_empty());
+        }
+    }
+}
@@ -5,13 +5,38 @@ use glib::clone;
 use gtk::prelude::*;
 
 use std::sync::{Arc, Mutex};
-use std::env;
-use std::fs;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-
-use crate::cmd::CalibreCmd;
-use crate::web::Article;
-use crate::config::PultConf;
+use std::rc::Rc;
+use std::path::Path;
+
+use url::Url;
+
+use crate::web::{epub_filename, ArticleImage, Extraction, HttpMode, PreviewBlock, THEMES};
+use crate::i18n::{tr, tr_args};
+use crate::config::{PultConf, DEVICE_PROFILES, OUT_FORMATS};
+use crate::delivery::{send_by_mail, TARGETS};
+use crate::job::BatchJob;
+
+mod advanced;
+mod clipboard;
+mod failure;
+mod history;
+mod inputs;
+mod queue;
+mod settings;
+mod shortcuts;
+mod wizard;
+#[cfg(feature = "tray")]
+mod tray;
+use advanced::AdvancedOptions;
+use clipboard::build_clipboard_bar;
+use history::build_history_page;
+use inputs::open_input;
+use queue::ConvQueue;
+use settings::{choice_combo, load_conf};
+use shortcuts::build_actions;
+use wizard::run_setup_wizard;
 
 struct CfgField {
     label: gtk::Label,
@@ -36,14 +61,218 @@ impl CfgField {
     }
 }
 
-struct CfgFields {
-    from_mail: CfgField,
-    to_mail: CfgField,
-    smtp: CfgField,
-    port: CfgField,
-    user: CfgField,
-    password: CfgField,
-    to_ext: CfgField,
+/// Open `path` with the default application for its type
+fn open_file(path: &Path) {
+    let uri = match path.canonicalize().ok().and_then(|p| Url::from_file_path(p).ok()) {
+        Some(uri) => uri,
+        None => { println!("File not found: {:?}", path); return },
+    };
+
+    if let Err(e) = gio::AppInfo::launch_default_for_uri(uri.as_str(), None::<&gio::AppLaunchContext>) {
+        println!("Can't open {}: {}", uri, e);
+    }
+}
+
+/// Combo box with a `default` entry, whose id is empty, followed by `choices`
+fn override_combo(default: &str, choices: &[&str]) -> gtk::ComboBoxText {
+    let combo = choice_combo(choices, "");
+    combo.prepend(Some(""), default);
+    combo.set_active_id(Some(""));
+    combo
+}
+
+/// Thumbnails of the article images with a check box each, to leave some out of the book
+fn build_image_picker(images: &[ArticleImage]) -> (gtk::ScrolledWindow, Vec<gtk::CheckButton>) {
+    let picker = gtk::FlowBox::new();
+    picker.set_selection_mode(gtk::SelectionMode::None);
+    picker.set_max_children_per_line(6);
+
+    let checks = images.iter().map(|img| {
+        let check = gtk::CheckButton::new();
+        check.set_active(img.included);
+        check.set_tooltip_text(Some(img.url.as_str()));
+        // Made while downloading, decoding images here would freeze the window
+        match &img.thumbnail {
+            Some(thumb) => check.add(&gtk::Image::from_file(thumb)),
+            None => check.set_label(&img.path.file_name().unwrap_or_default().to_string_lossy()),
+        }
+        picker.add(&check);
+        check
+    }).collect();
+
+    let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled.set_min_content_height(if images.is_empty() { 0 } else { 130 });
+    scrolled.add(&picker);
+
+    (scrolled, checks)
+}
+
+/// Read-only text view for the built-in preview, hidden until asked for
+fn build_preview_pane() -> (gtk::ScrolledWindow, gtk::TextView) {
+    let heading = gtk::TextTag::new(Some("heading"));
+    heading.set_property_weight(700);
+    heading.set_property_scale(1.3);
+    let quote = gtk::TextTag::new(Some("quote"));
+    quote.set_property_left_margin(30);
+    quote.set_property_foreground(Some("dim gray"));
+    let tags = gtk::TextTagTable::new();
+    tags.add(&heading);
+    tags.add(&quote);
+
+    let view = gtk::TextView::with_buffer(&gtk::TextBuffer::new(Some(&tags)));
+    view.set_editable(false);
+    view.set_cursor_visible(false);
+    view.set_wrap_mode(gtk::WrapMode::Word);
+    view.set_left_margin(10);
+    view.set_right_margin(10);
+    view.set_pixels_below_lines(8);
+
+    let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled.set_min_content_height(300);
+    scrolled.add(&view);
+    scrolled.set_no_show_all(true);
+
+    (scrolled, view)
+}
+
+/// Fill the preview `view` with the article text and the thumbnails of the images left in
+fn show_preview(view: &gtk::TextView, extraction: &Extraction) {
+    let buffer = match view.get_buffer() {
+        Some(buffer) => buffer,
+        None => return,
+    };
+    buffer.set_text("");
+
+    for block in extraction.preview() {
+        let (text, tag) = match block {
+            PreviewBlock::Heading(text) => (text, Some("heading")),
+            PreviewBlock::Paragraph(text) => (text, None),
+            PreviewBlock::Quote(text) => (text, Some("quote")),
+            PreviewBlock::Image(url) => {
+                let thumbnail = extraction.images.iter()
+                    .find(|img| img.urls().any(|img_url| *img_url == url))
+                    .filter(|img| img.included)
+                    .and_then(|img| img.thumbnail.as_ref());
+                if let Some(thumb) = thumbnail {
+                    if let Some(anchor) = buffer.create_child_anchor(&mut buffer.get_end_iter()) {
+                        view.add_child_at_anchor(&gtk::Image::from_file(thumb), &anchor);
+                        buffer.insert(&mut buffer.get_end_iter(), "\n");
+                    }
+                }
+                continue
+            },
+        };
+
+        let start = buffer.get_end_iter().get_offset();
+        buffer.insert(&mut buffer.get_end_iter(), &format!("{}\n", text));
+        if let Some(tag) = tag {
+            buffer.apply_tag_by_name(tag, &buffer.get_iter_at_offset(start), &buffer.get_end_iter());
+        }
+    }
+    view.show_all();
+}
+
+const PREVIEW: gtk::ResponseType = gtk::ResponseType::Other(1);
+
+/// Show what was extracted, let the user fix its metadata and ask whether to go on
+fn confirm_preview(win: &gtk::ApplicationWindow, extraction: &mut Extraction) -> bool {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(tr("article-preview")),
+        Some(win),
+        gtk::DialogFlags::MODAL,
+        &[(tr("preview"), PREVIEW), (tr("convert"), gtk::ResponseType::Ok), (tr("cancel"), gtk::ResponseType::Cancel)]
+    );
+
+    let article = &extraction.article;
+
+    // Editable metadata
+    let title = CfgField::new(tr("title-label"), article.title());
+    let author = CfgField::new(tr("author-label"), article.byline());
+    let tags = CfgField::new(tr("tags-label"), &article.tags().join(", "));
+    let filename = CfgField::new(tr("filename-label"), &extraction.filename);
+    let delivery_lbl = gtk::Label::new(Some(tr("deliver-to-label")));
+    let delivery = choice_combo(TARGETS, &extraction.delivery);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(10);
+    grid.set_column_spacing(10);
+    for (row, field) in [&title, &author, &tags, &filename].iter().enumerate() {
+        grid.attach(&field.label, 0, row as i32, 1, 1);
+        grid.attach(&field.entry, 1, row as i32, 1, 1);
+    }
+    grid.attach(&delivery_lbl, 0, 4, 1, 1);
+    grid.attach(&delivery, 1, 4, 1, 1);
+
+    let date = gtk::Label::new(Some(article.date()));
+
+    let summary = gtk::Label::new(Some(&article.summary(3)));
+    summary.set_line_wrap(true);
+    summary.set_max_width_chars(80);
+
+    let images = gtk::Label::new(Some(&tr_args("images-detected", &[("count", extraction.images.len())])));
+    let (picker, checks) = build_image_picker(&extraction.images);
+    let (preview, preview_view) = build_preview_pane();
+
+    let content = dialog.get_content_area();
+    content.set_spacing(10);
+    content.set_margin_top(10);
+    content.set_margin_start(10);
+    content.set_margin_end(10);
+    content.add(&grid);
+    content.add(&date);
+    content.add(&summary);
+    content.add(&images);
+    content.add(&picker);
+    content.add(&preview);
+
+    dialog.show_all();
+    let response = loop {
+        match dialog.run() {
+            // Show the article below the metadata, the dialog stays open
+            PREVIEW => {
+                for (img, check) in extraction.images.iter_mut().zip(&checks) {
+                    img.included = check.get_active();
+                }
+                show_preview(&preview_view, extraction);
+                preview.show();
+            },
+            response => break response,
+        }
+    };
+    dialog.close();
+
+    if response != gtk::ResponseType::Ok {
+        return false
+    }
+
+    extraction.article.set_title(title.buffer.get_text());
+    extraction.article.set_byline(author.buffer.get_text());
+    extraction.article.set_tags(
+        tags.buffer.get_text().split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    );
+    // Typed names can hold path separators or lack the extension
+    if let Some(name) = epub_filename(&filename.buffer.get_text()) {
+        extraction.filename = name;
+    }
+    for (img, check) in extraction.images.iter_mut().zip(&checks) {
+        img.included = check.get_active();
+    }
+
+    // The picked target becomes the default for the next conversions
+    if let Some(target) = delivery.get_active_id().filter(|t| *t != extraction.delivery) {
+        extraction.delivery = target.to_string();
+        if let Some(mut conf) = load_conf(win) {
+            conf.delivery = extraction.delivery.clone();
+            if let Err(e) = confy::store("kindle-pult", conf) {
+                println!("Can't save settings: {:?}", e);
+            }
+        }
+    }
+
+    true
 }
 
 pub struct Gui {
@@ -52,11 +281,12 @@ pub struct Gui {
     file_img: gtk::Image,
     source_files: Arc<Mutex<Vec<std::path::PathBuf>>>,  // TODO: use RefCell
     open_sender: glib::Sender<Vec<std::path::PathBuf>>,
-    cfg: HashMap<String, String>,
+    queue: Rc<RefCell<ConvQueue>>,
+    watch_clipboard: Rc<Cell<bool>>,
 }
 
 impl Gui {
-    pub fn new(application: &gtk::Application) -> Self {
+    pub fn new(application: &gtk::Application, http_mode: HttpMode) -> Self {
         // Main window
         let win = gtk::ApplicationWindow::new(application);
 
@@ -66,7 +296,6 @@ impl Gui {
         vbox.set_margin_start(10 as i32);
         vbox.set_margin_end(10 as i32);
         vbox.set_margin_bottom(10 as i32);
-        win.add(&vbox);
 
         // Images
         let file_img = gtk::Image::from_icon_name(Some("document-open"), gtk::IconSize::Button);
@@ -86,8 +315,16 @@ impl Gui {
             glib::Continue(true)
         });
 
-        // Reload Conf
-        let cfg = PultConf::reload();
+        let queue = ConvQueue::new(&win, http_mode);
+        let watch_clipboard = Rc::new(Cell::new(
+            PultConf::reload().get("watch_clipboard").unwrap().parse().unwrap_or(false)
+        ));
+
+        // Tabs: conversion and history
+        let notebook = gtk::Notebook::new();
+        notebook.append_page(&vbox, Some(&gtk::Label::new(Some(tr("convert")))));
+        notebook.append_page(&build_history_page(&queue), Some(&gtk::Label::new(Some(tr("history")))));
+        win.add(&notebook);
 
         Self {
             win,
@@ -95,7 +332,8 @@ impl Gui {
             file_img,
             source_files,
             open_sender,
-            cfg,
+            queue,
+            watch_clipboard,
         }
     }
 
@@ -110,14 +348,14 @@ impl Gui {
         let sender_clone = self.open_sender.clone();
         select_files_btn.connect_clicked(clone!(@weak win => move |_| {
             let dialog = gtk::FileChooserDialog::new(
-                Some("Choose a file"),
+                Some(tr("choose-a-file")),
                 Some(&win),
                 gtk::FileChooserAction::Open
             );
 
             dialog.add_buttons(&[
-                ("Open", gtk::ResponseType::Ok),
-                ("Cancel", gtk::ResponseType::Cancel)
+                (tr("open"), gtk::ResponseType::Ok),
+                (tr("cancel"), gtk::ResponseType::Cancel)
             ]);
 
             dialog.set_select_multiple(true);
@@ -135,7 +373,21 @@ impl Gui {
 
         select_files_btn.grab_focus();
 
+        // Settings dialog
+        let settings_btn = gtk::Button::from_icon_name(Some("preferences-system"), gtk::IconSize::Button);
+        settings_btn.set_action_name(Some("win.settings"));
+        settings_btn.set_tooltip_text(Some(tr("settings")));
+
         headerbar.add(&select_files_btn);  // Add select button to headerbar
+        headerbar.pack_end(&settings_btn);
+
+        // Pause the conversion queue
+        let pause_btn = gtk::ToggleButton::new();
+        pause_btn.add(&gtk::Image::from_icon_name(Some("media-playback-pause"), gtk::IconSize::Button));
+        pause_btn.set_tooltip_text(Some(tr("pause-queue")));
+        let queue = Rc::clone(&self.queue);
+        pause_btn.connect_toggled(move |btn| ConvQueue::set_paused(&queue, btn.get_active()));
+        headerbar.pack_end(&pause_btn);
         headerbar.set_show_close_button(true);  // Show close/extend/minimize in headerbar
         self.win.set_titlebar(Some(&headerbar));  // Set this headerbar as title bar (the top one)
 
@@ -145,169 +397,147 @@ impl Gui {
     fn build_url_box(&self) {
         let url_box = gtk::Box::new(gtk::Orientation::Horizontal, 10 as i32);
 
-        let url_field = CfgField::new("URL:", "");
+        let url_field = CfgField::new(tr("url-label"), "");
         url_field.entry.set_size_request(420, 20);
         url_field.entry.set_hexpand(true);
 
-        let download_btn = gtk::Button::with_label("Download");
+        let download_btn = gtk::Button::with_label(tr("download"));
         download_btn.set_property_expand(false);
 
+        // Per-conversion overrides, "Default" keeps the configured value
+        let theme = override_combo(tr("default-theme"), THEMES);
+        let format = override_combo(tr("default-format"), OUT_FORMATS);
+        let device_names: Vec<&str> = DEVICE_PROFILES.iter().map(|(name, _)| *name).collect();
+        let device = override_combo(tr("default-device"), &device_names);
+        let advanced = Rc::new(AdvancedOptions::new());
+
         let url_buffer_clone = url_field.buffer.clone();
+        let queue = Rc::clone(&self.queue);
+        let combos = [("css_theme", theme.clone()), ("out_format", format.clone()), ("device", device.clone())];
+        let advanced_clone = Rc::clone(&advanced);
         download_btn.connect_clicked(move |_| {
-            let _article = Article::epub_from_url(url_buffer_clone.get_text());
-            // println!("{:?}", no.unwrap());
+            let url = url_buffer_clone.get_text();
+            let mut overrides: HashMap<String, String> = combos.iter()
+                .filter_map(|(key, combo)| {
+                    let id = combo.get_active_id().filter(|id| !id.is_empty())?;
+                    Some((key.to_string(), id.to_string()))
+                })
+                .collect();
+            overrides.extend(advanced_clone.overrides());
+
+            // Converted here even with a daemon running: preview, metadata and image picker
+            // need the GUI. `kindle-pult add` hands URLs to the daemon without them.
+            ConvQueue::add_with(&queue, url, overrides);
+            url_buffer_clone.set_text("");
         });  // Connect clicked button
 
         url_box.add(&url_field.label);
         url_box.add(&url_field.entry);
+        url_box.add(&theme);
+        url_box.add(&format);
+        url_box.add(&device);
         url_box.add(&download_btn);
-        url_box.set_margin_bottom(20);
 
         self.vbox.add(&url_box);
+        self.vbox.add(advanced.widget());
+
+        // Conversion queue, below the URL field
+        let queue = self.queue.borrow();
+        queue.widget().set_margin_bottom(20);
+        self.vbox.add(queue.widget());
     }
 
-    fn make_cfg_fields(&self) -> CfgFields {
-        CfgFields {
-            from_mail: CfgField::new("From:", self.cfg.get("from_mail").unwrap()),
-            to_mail: CfgField::new("To:", self.cfg.get("to_mail").unwrap()),
-            smtp: CfgField::new("Protocol:", self.cfg.get("smtp").unwrap()),
-            port: CfgField::new("Port:", self.cfg.get("port").unwrap()),
-            user: CfgField::new("User:", self.cfg.get("username").unwrap()),
-            password: CfgField::new("Password:", self.cfg.get("password").unwrap()),
-            to_ext: CfgField::new("Extension:", self.cfg.get("to_ext").unwrap()),
-        }
+    /// Add files to send, e.g. the remaining ones of a resumed job
+    pub fn queue_files(&self, files: Vec<std::path::PathBuf>) {
+        let mut m = self.source_files.lock().unwrap();
+        m.extend(files);
     }
 
-    fn build_cfg_ui(&self, flds: CfgFields) {
-        let grid = gtk::Grid::new();
-
-        // Grid spacing
-        grid.set_row_homogeneous(true);
-        // grid.set_column_homogeneous(true);
-        grid.set_row_spacing(10);
-        grid.set_column_spacing(10);
-        grid.set_margin_bottom(20);
-
-        // Row 0
-        grid.attach(&flds.from_mail.label, 0, 0, 1, 1);  // Col 0
-        grid.attach(&flds.from_mail.entry, 1, 0, 1, 1);  // Col 1
-        grid.attach(&flds.to_mail.label, 2, 0, 1, 1);  // Col 2
-        grid.attach(&flds.to_mail.entry, 3, 0, 1, 1);  // Col 3
-
-        // Row 1
-        grid.attach(&flds.smtp.label, 0, 1, 1, 1);
-        grid.attach(&flds.smtp.entry, 1, 1, 1, 1);
-        grid.attach(&flds.port.label, 2, 1, 1, 1);
-        grid.attach(&flds.port.entry, 3, 1, 1, 1);
-
-        // Row 2
-        grid.attach(&flds.user.label, 0, 2, 1, 1);
-        grid.attach(&flds.user.entry, 1, 2, 1, 1);
-        grid.attach(&flds.password.label, 2, 2, 1, 1);
-        grid.attach(&flds.password.entry, 3, 2, 1, 1);
-
-        &flds.password.entry.set_visibility(false);
-
-        // Row 3
-        grid.attach(&flds.to_ext.label, 0, 3, 1, 1);
-        grid.attach(&flds.to_ext.entry, 1, 3, 1, 1);
-
-        let del_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
-        let del_sent = gtk::Switch::new();
-        let del_sent_lbl = gtk::Label::new(Some("Delete sents"));
-        del_sent.set_active(self.cfg.get("del_sent").unwrap().parse().unwrap());
-        del_box.add(&del_sent_lbl);
-        del_box.add(&del_sent);
-        // grid.attach(&del_sent_lbl, 2, 3, 1, 1);
-        grid.attach(&del_box, 2, 3, 1, 1);
-
-        self.vbox.add(&grid);
-
-        // Cfg Button Box
-        // let btn_box = gtk::ButtonBox::new(gtk::Orientation::Horizontal);
-        // btn_box.set_margin_top(10 as i32);
-        // btn_box.set_layout(gtk::ButtonBoxStyle::Spread);
-
-        // Save button
-        let save_button = gtk::Button::with_label("Save settings");
-        save_button.set_property_expand(false);
-
-        save_button.connect_clicked(move |_| {
-            let new_conf = PultConf {
-                del_sent: {if del_sent.get_state() { "true".into() } else { "false".into() }},
-                to_ext: flds.to_ext.buffer.get_text(),
-                smtp: flds.smtp.buffer.get_text(),
-                port: flds.port.buffer.get_text(),
-                username: flds.user.buffer.get_text(),
-                password: flds.password.buffer.get_text(),
-                from_mail: flds.from_mail.buffer.get_text(),
-                to_mail: flds.to_mail.buffer.get_text(),
-            };
-
-            let _ = confy::store("kindle-pult", new_conf);
-            let _ = PultConf::reload();
-        });  // Connect clicked button
+    /// Start conversions for URLs or files handed over by the desktop, e.g. dropped on the dock icon
+    pub fn open_inputs(&self, inputs: &[String]) {
+        for input in inputs {
+            open_input(input, &self.queue, &self.source_files);
+        }
+    }
 
-        // btn_box.add(&save_button);
-        grid.attach(&save_button, 3, 3, 1, 1);
-        // self.vbox.add(&btn_box);
-    }  // build_cfg_ui
+    /// Accept URLs and files dropped on the window
+    fn build_drop_target(&self) {
+        let targets = [
+            gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::OTHER_APP, 0),
+            gtk::TargetEntry::new("text/plain", gtk::TargetFlags::OTHER_APP, 1),
+        ];
+        self.win.drag_dest_set(gtk::DestDefaults::ALL, &targets, gdk::DragAction::COPY);
+
+        let queue = Rc::clone(&self.queue);
+        let source_files = Arc::clone(&self.source_files);
+        self.win.connect_drag_data_received(move |_, _, _, _, data, _, _| {
+            let uris = data.get_uris();
+            if !uris.is_empty() {
+                for uri in uris {
+                    open_input(&uri, &queue, &source_files);
+                }
+            } else if let Some(text) = data.get_text() {
+                for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                    open_input(line, &queue, &source_files);
+                }
+            }
+        });
+    }
 
     pub fn build(&self) {
+        // Actions and keyboard shortcuts, also used by the headerbar buttons
+        build_actions(&self.win, &self.queue, &self.source_files, &self.watch_clipboard);
+
         // HeaderBar
         self.build_headerbar();
 
+        // Drag and drop
+        self.build_drop_target();
+
+        // Copied URLs
+        self.vbox.add(&build_clipboard_bar(&self.queue, Rc::clone(&self.watch_clipboard)));
+
         // URL Area
         self.build_url_box();
 
-        // Cfg Area
-        let cfg_fields = self.make_cfg_fields();
-        self.build_cfg_ui(cfg_fields);
-
         // Send button
-        let send_button = gtk::Button::with_label("Send");
+        let send_button = gtk::Button::with_label(tr("send"));
 
         let source_files_clone = Arc::clone(&self.source_files);
-        let cfg_clone = self.cfg.clone();
         send_button.connect_clicked(move |_| {  // On clicked send button...
+            let cfg_clone = PultConf::reload();
             let files = source_files_clone.lock().unwrap();
+            let mut job = BatchJob::new(files.clone());
+            job.save();
 
             for file in &*files {
-                // Check file and its extension
-                if file.exists() {
-                    // CD in file directory
-                    let file_dir = file.parent().unwrap();
-                    let _cd_success = env::set_current_dir(&file_dir);
-                    let from_ext = file.extension().unwrap().to_str().unwrap();
-                    let to_ext = cfg_clone.get("to_ext").unwrap();
-
-                    if from_ext == to_ext {
-                        println!("Conversion unnecessary");
-                    } else {
-                        let _conv_output = CalibreCmd::convert(file.to_str().unwrap(), &to_ext);
-                    }
-                } else {
-                    println!("File not found.");
-                    return
-                }
-
-                let filename = file.file_stem().unwrap();
-                let _send_output = CalibreCmd::send(filename.to_str().unwrap(), cfg_clone.clone());
-
-                // Delete sent file?
-                if cfg_clone.get("del_sent").unwrap().parse().unwrap() {
-                    let _del_result = fs::remove_file(file);
+                // Only mails that went out are done, the others are retried by --resume
+                match send_by_mail(file, &cfg_clone) {
+                    Ok(()) => job.mark_done(file),
+                    Err(e) => {
+                        println!("Can't send {:?}: {}", file, e);
+                        job.mark_failed(file);
+                    },
                 }
             }
+
+            job.finish();
         });
 
         send_button.set_property_expand(false);
         send_button.set_widget_name("suggested-action");  // Mark as primary
         self.vbox.add(&send_button);
 
+        #[cfg(feature = "tray")]
+        tray::build_tray(&self.win, &self.queue);
+
         // Win final settings
         self.win.set_title("Kindle-pult");
         self.win.set_position(gtk::WindowPosition::Center);
         self.win.show_all();
+
+        if PultConf::reload().get("setup_done").unwrap() != "true" {
+            run_setup_wizard(&self.win, &self.queue);
+        }
     }
 }
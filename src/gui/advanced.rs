@@ -0,0 +1,86 @@
+use gtk::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::i18n::tr;
+
+/// Less common per-article options, folded under the URL field
+pub struct AdvancedOptions {
+    expander: gtk::Expander,
+    strip_images: gtk::CheckButton,
+    endnotes: gtk::CheckButton,
+    custom_css: gtk::FileChooserButton,
+    snapshot_date: gtk::Entry,
+    description: gtk::Entry,
+    lang: gtk::Entry,
+}
+
+impl AdvancedOptions {
+    pub fn new() -> Self {
+        let strip_images = gtk::CheckButton::with_label(tr("strip-images"));
+        let endnotes = gtk::CheckButton::with_label(tr("turn-links-into-endnotes"));
+
+        let custom_css = gtk::FileChooserButton::new(tr("custom-css"), gtk::FileChooserAction::Open);
+        let css_filter = gtk::FileFilter::new();
+        css_filter.add_pattern("*.css");
+        custom_css.set_filter(&css_filter);
+
+        let snapshot_date = gtk::Entry::new();
+        snapshot_date.set_placeholder_text(Some(tr("as-found-in-the-page")));
+        let description = gtk::Entry::new();
+        let lang = gtk::Entry::new();
+        lang.set_placeholder_text(Some("en"));
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(10);
+        grid.set_column_spacing(10);
+        grid.set_margin_top(10);
+        grid.attach(&strip_images, 0, 0, 2, 1);
+        grid.attach(&endnotes, 2, 0, 2, 1);
+
+        let rows: &[&[(&str, &gtk::Widget)]] = &[
+            &[(tr("custom-css-label"), custom_css.upcast_ref()), (tr("date-label"), snapshot_date.upcast_ref())],
+            &[(tr("description-label"), description.upcast_ref()), (tr("language-label"), lang.upcast_ref())],
+        ];
+        for (row, pairs) in rows.iter().enumerate() {
+            for (col, (label, widget)) in pairs.iter().enumerate() {
+                grid.attach(&gtk::Label::new(Some(label)), col as i32 * 2, row as i32 + 1, 1, 1);
+                grid.attach(*widget, col as i32 * 2 + 1, row as i32 + 1, 1, 1);
+            }
+        }
+
+        let expander = gtk::Expander::new(Some(tr("advanced")));
+        expander.add(&grid);
+
+        Self { expander, strip_images, endnotes, custom_css, snapshot_date, description, lang }
+    }
+
+    pub fn widget(&self) -> &gtk::Expander {
+        &self.expander
+    }
+
+    /// Config overrides for the options set by the user
+    pub fn overrides(&self) -> HashMap<String, String> {
+        let mut overrides = HashMap::new();
+
+        if self.strip_images.get_active() {
+            overrides.insert("skip_images".into(), "true".into());
+        }
+        if self.endnotes.get_active() {
+            overrides.insert("endnotes".into(), "true".into());
+        }
+        if let Some(css) = self.custom_css.get_filename() {
+            overrides.insert("custom_css".into(), css.to_string_lossy().to_string());
+        }
+
+        let entries = [("snapshot_date", &self.snapshot_date), ("description", &self.description), ("lang", &self.lang)];
+        for (key, entry) in &entries {
+            let text = entry.get_text().trim().to_string();
+            if !text.is_empty() {
+                overrides.insert(key.to_string(), text);
+            }
+        }
+
+        overrides
+    }
+}
@@ -0,0 +1,70 @@
+use gtk::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use url::Url;
+
+use crate::i18n::tr;
+use super::queue::ConvQueue;
+
+/// Bar offering to convert URLs copied to the clipboard, polled while `enabled` is set
+pub fn build_clipboard_bar(queue: &Rc<RefCell<ConvQueue>>, enabled: Rc<Cell<bool>>) -> gtk::InfoBar {
+    let bar = gtk::InfoBar::new();
+    bar.set_message_type(gtk::MessageType::Question);
+    bar.set_show_close_button(true);
+    bar.set_no_show_all(true);  // Hidden until a URL is copied
+
+    let label = gtk::Label::new(None);
+    label.set_line_wrap(true);
+    bar.get_content_area().add(&label);
+    label.show();
+    bar.add_button(tr("send-to-kindle"), gtk::ResponseType::Accept);
+
+    let offered = Rc::new(RefCell::new(String::new()));  // URL shown in the bar
+
+    let queue = Rc::clone(queue);
+    let offered_clone = Rc::clone(&offered);
+    bar.connect_response(move |bar, response| {
+        if response == gtk::ResponseType::Accept {
+            ConvQueue::add(&queue, offered_clone.borrow().clone());
+        }
+        bar.hide();
+    });
+
+    let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+    let last_seen = Rc::new(RefCell::new(String::new()));
+    let bar_clone = bar.clone();
+    glib::timeout_add_seconds_local(1, move || {
+        if !enabled.get() {
+            return glib::Continue(true)
+        }
+
+        let (bar, label, offered, last_seen) =
+            (bar_clone.clone(), label.clone(), Rc::clone(&offered), Rc::clone(&last_seen));
+        clipboard.request_text(move |_, text| {
+            let text = match text {
+                Some(text) => text.trim().to_string(),
+                None => return,
+            };
+
+            // Offer each copied URL only once
+            if *last_seen.borrow() == text {
+                return
+            }
+            *last_seen.borrow_mut() = text.clone();
+
+            if let Ok(url) = Url::parse(&text) {
+                if url.scheme() == "http" || url.scheme() == "https" {
+                    label.set_text(&format!("{} {}", tr("copied"), url));
+                    *offered.borrow_mut() = url.to_string();
+                    bar.show();
+                }
+            }
+        });
+
+        glib::Continue(true)
+    });
+
+    bar
+}
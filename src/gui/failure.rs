@@ -0,0 +1,69 @@
+use gtk::prelude::*;
+
+use crate::i18n::tr;
+
+/// A failed conversion, as shown to the user
+pub struct Failure {
+    pub url: String,
+    pub stage: String,  // Last reached pipeline step
+    pub message: String,
+    pub explanation: &'static str,
+}
+
+impl Failure {
+    /// Plain text report to paste in a bug report
+    fn diagnostics(&self) -> String {
+        format!(
+            "kindle-pult {}\nOS: {}\nURL: {}\nStage: {}\nError: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            self.url,
+            self.stage,
+            self.message,
+        )
+    }
+}
+
+/// What the user wants to do about a failure
+pub enum FailureAction {
+    Retry,
+    RetryWithoutImages,
+    Close,
+}
+
+const RETRY: gtk::ResponseType = gtk::ResponseType::Other(1);
+const RETRY_WITHOUT_IMAGES: gtk::ResponseType = gtk::ResponseType::Other(2);
+const COPY: gtk::ResponseType = gtk::ResponseType::Other(3);
+
+/// Explain `failure` and ask what to do, diagnostics can be copied without closing the dialog
+pub fn run_failure_dialog(win: &gtk::ApplicationWindow, failure: &Failure) -> FailureAction {
+    let dialog = gtk::MessageDialog::new(
+        Some(win),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::None,
+        tr("conversion-failed"),
+    );
+    dialog.set_property_secondary_text(Some(&format!(
+        "{}\n\nURL: {}\n{}: {}\n{}: {}",
+        failure.explanation, failure.url, tr("stage"), failure.stage, tr("error"), failure.message,
+    )));
+    dialog.add_buttons(&[
+        (tr("copy-diagnostics"), COPY),
+        (tr("retry-without-images"), RETRY_WITHOUT_IMAGES),
+        (tr("retry"), RETRY),
+        (tr("close"), gtk::ResponseType::Close),
+    ]);
+
+    let action = loop {
+        match dialog.run() {
+            COPY => gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&failure.diagnostics()),
+            RETRY => break FailureAction::Retry,
+            RETRY_WITHOUT_IMAGES => break FailureAction::RetryWithoutImages,
+            _ => break FailureAction::Close,
+        }
+    };
+    dialog.close();
+
+    action
+}
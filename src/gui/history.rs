@@ -0,0 +1,89 @@
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::config::PultConf;
+use crate::delivery::send_by_mail;
+use crate::history::{History, HistoryEntry};
+use crate::i18n::tr;
+use super::queue::ConvQueue;
+use super::open_file;
+
+fn build_row(entry: &HistoryEntry, queue: &Rc<RefCell<ConvQueue>>) -> gtk::ListBoxRow {
+    let date = glib::DateTime::from_unix_local(entry.timestamp as i64)
+        .format("%Y-%m-%d %H:%M")
+        .map(|d| d.to_string())
+        .unwrap_or_default();
+
+    let title = gtk::Label::new(Some(&entry.title));
+    title.set_hexpand(true);
+    title.set_halign(gtk::Align::Start);
+    title.set_tooltip_text(Some(&entry.url));
+
+    let send_btn = gtk::Button::from_icon_name(Some("mail-send-symbolic"), gtk::IconSize::Button);
+    send_btn.set_tooltip_text(Some(tr("send-to-device")));
+    let path = entry.path.clone();
+    send_btn.connect_clicked(move |_| {
+        if let Err(e) = send_by_mail(&path, &PultConf::reload()) {
+            println!("Can't send {:?}: {}", path, e);
+        }
+    });
+
+    let convert_btn = gtk::Button::from_icon_name(Some("view-refresh-symbolic"), gtk::IconSize::Button);
+    convert_btn.set_tooltip_text(Some(tr("convert-again")));
+    let (url, queue) = (entry.url.clone(), Rc::clone(queue));
+    convert_btn.connect_clicked(move |_| ConvQueue::add(&queue, url.clone()));
+
+    let open_btn = gtk::Button::from_icon_name(Some("document-open-symbolic"), gtk::IconSize::Button);
+    open_btn.set_tooltip_text(Some(tr("open")));
+    open_btn.set_sensitive(entry.path.exists());
+    let path = entry.path.clone();
+    open_btn.connect_clicked(move |_| open_file(&path));
+
+    let copy_btn = gtk::Button::from_icon_name(Some("edit-copy-symbolic"), gtk::IconSize::Button);
+    copy_btn.set_tooltip_text(Some(tr("copy-source-url")));
+    let url = entry.url.clone();
+    copy_btn.connect_clicked(move |_| gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&url));
+
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+    hbox.add(&title);
+    hbox.add(&gtk::Label::new(Some(&date)));
+    hbox.add(&send_btn);
+    hbox.add(&convert_btn);
+    hbox.add(&open_btn);
+    hbox.add(&copy_btn);
+
+    let row = gtk::ListBoxRow::new();
+    row.add(&hbox);
+    row
+}
+
+/// Fill `list` with the saved history, newest first
+fn fill_history(list: &gtk::ListBox, queue: &Rc<RefCell<ConvQueue>>) {
+    for row in list.get_children() {
+        list.remove(&row);
+    }
+    for entry in History::load().entries {
+        list.add(&build_row(&entry, queue));
+    }
+    list.show_all();
+}
+
+/// History page listing past conversions, updated whenever the queue saves an EPUB
+pub fn build_history_page(queue: &Rc<RefCell<ConvQueue>>) -> gtk::ScrolledWindow {
+    let list = gtk::ListBox::new();
+    list.set_selection_mode(gtk::SelectionMode::None);
+    fill_history(&list, queue);
+
+    let weak = Rc::downgrade(queue);
+    let list_clone = list.clone();
+    queue.borrow_mut().connect_saved(move |_, _| {
+        if let Some(queue) = weak.upgrade() { fill_history(&list_clone, &queue); }
+    });
+
+    let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled.set_min_content_height(300);
+    scrolled.add(&list);
+    scrolled
+}
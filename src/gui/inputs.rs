@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use url::Url;
+
+use super::queue::ConvQueue;
+use crate::mail::html_from_eml;
+use crate::paths;
+
+/// Route a dropped or opened input to its handler: web pages and local HTML files go to the
+/// conversion queue, mails are turned into HTML first, anything else (Markdown, ebooks,
+/// documents) is added to the files to convert and send with Calibre.
+/// `kindle-pult://convert?url=...` links come from any web page, so only their web `url` is
+/// queued: never local files, nor further links.
+pub fn open_input(input: &str, queue: &Rc<RefCell<ConvQueue>>, source_files: &Arc<Mutex<Vec<PathBuf>>>) {
+    let input = input.trim();
+    let path = match Url::parse(input) {
+        Ok(url) if url.scheme() == "kindle-pult" => {
+            let target = url.query_pairs()
+                .find(|(key, _)| key == "url")
+                .and_then(|(_, target)| Url::parse(target.trim()).ok());
+            match target {
+                Some(target) if target.scheme() == "http" || target.scheme() == "https" => {
+                    ConvQueue::add(queue, target.to_string())
+                },
+                _ => println!("Nothing to convert in {}", url),
+            }
+            return
+        },
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+            ConvQueue::add(queue, url.to_string());
+            return
+        },
+        Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+            Ok(path) => path,
+            Err(_) => { println!("Invalid file URL: {}", url); return },
+        },
+        _ => PathBuf::from(input),
+    };
+
+    if !path.is_file() {
+        println!("Can't open {}", input);
+        return
+    }
+
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    match ext.as_str() {
+        "html" | "htm" | "xhtml" => queue_local_page(queue, &path),
+        "eml" => match html_from_eml(&path, &paths::cache_dir()) {
+            Ok(html) => queue_local_page(queue, &html),
+            Err(e) => println!("Can't read mail {:?}: {}", path, e),
+        },
+        _ => source_files.lock().unwrap().push(path),
+    }
+}
+
+fn queue_local_page(queue: &Rc<RefCell<ConvQueue>>, path: &Path) {
+    match path.canonicalize().ok().and_then(|p| Url::from_file_path(p).ok()) {
+        Some(url) => ConvQueue::add(queue, url.to_string()),
+        None => println!("File not found: {:?}", path),
+    }
+}
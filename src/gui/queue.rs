@@ -0,0 +1,457 @@
+use gtk::prelude::*;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+
+use crate::config::PultConf;
+use crate::history::History;
+use crate::i18n::tr;
+use crate::web::{explain_error, Article, Extraction, HttpMode, Progress};
+use super::failure::{run_failure_dialog, Failure, FailureAction};
+use super::{confirm_preview, open_file};
+
+/// Messages from conversion threads to the GUI
+enum ConvMsg {
+    Step(Progress),
+    Extracted(Box<Extraction>),
+    Saved(String, PathBuf),  // Title and path of the built EPUB
+    Failed(String, &'static str),  // Error and its explanation
+}
+
+/// Failure message for a conversion thread that panicked, so the queue doesn't wait for it forever
+fn crashed(panic: Box<dyn Any + Send>) -> ConvMsg {
+    let message = panic.downcast_ref::<&str>().map(|m| m.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "conversion crashed".into());
+    ConvMsg::Failed(message, tr("error-crashed"))
+}
+
+/// Conversion states shown in the queue
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum JobState {
+    Waiting,
+    Downloading,
+    Building,
+    Delivered,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn from_progress(p: &Progress) -> Self {
+        match p {
+            Progress::Fetch | Progress::Extract | Progress::Images(..) => JobState::Downloading,
+            Progress::Build | Progress::Deliver => JobState::Building,
+            Progress::Done => JobState::Delivered,
+            Progress::Failed(_) => JobState::Failed,
+        }
+    }
+}
+
+/// A URL in the queue and its row in the list
+struct QueueItem {
+    id: usize,
+    attempt: usize,  // Messages from older attempts of the same item are ignored
+    url: String,
+    state: JobState,
+    row: gtk::ListBoxRow,
+    bar: gtk::ProgressBar,
+    cancel_btn: gtk::Button,
+    retry_btn: gtk::Button,
+    open_btn: gtk::Button,
+    path: Option<PathBuf>,  // Saved EPUB
+    stage: String,  // Label of the last pipeline step reached
+    overrides: HashMap<String, String>,  // Config values for this item only
+}
+
+impl QueueItem {
+    fn set_state(&mut self, state: JobState, text: &str) {
+        self.state = state;
+        self.bar.set_text(Some(&format!("{}: {}", self.url, text)));
+
+        // Once building, the user already confirmed the preview
+        self.cancel_btn.set_sensitive(matches!(state, JobState::Waiting | JobState::Downloading));
+        self.retry_btn.set_sensitive(matches!(state, JobState::Failed | JobState::Cancelled));
+        self.open_btn.set_sensitive(state == JobState::Delivered);
+    }
+}
+
+type SavedHandler = Box<dyn Fn(&str, &Path)>;
+
+/// Conversion queue, URLs are converted one at a time in list order
+pub struct ConvQueue {
+    win: gtk::ApplicationWindow,
+    list: gtk::ListBox,
+    http_mode: HttpMode,
+    items: Vec<QueueItem>,
+    next_id: usize,
+    running: Option<usize>,  // id of the item being converted
+    paused: bool,  // Don't start waiting items
+    recent: Vec<(String, PathBuf)>,  // Title and path of saved EPUBs, newest first
+    saved_handlers: Vec<SavedHandler>,
+}
+
+impl ConvQueue {
+    pub fn new(win: &gtk::ApplicationWindow, http_mode: HttpMode) -> Rc<RefCell<Self>> {
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+
+        Rc::new(RefCell::new(Self {
+            win: win.clone(),
+            list,
+            http_mode,
+            items: Vec::new(),
+            next_id: 0,
+            running: None,
+            paused: false,
+            recent: Vec::new(),
+            saved_handlers: Vec::new(),
+        }))
+    }
+
+    pub fn widget(&self) -> &gtk::ListBox {
+        &self.list
+    }
+
+    #[cfg(feature = "tray")]
+    pub fn recent(&self) -> &[(String, PathBuf)] {
+        &self.recent
+    }
+
+    /// Call `f` with title and path of every EPUB saved from now on
+    pub fn connect_saved<F: Fn(&str, &Path) + 'static>(&mut self, f: F) {
+        self.saved_handlers.push(Box::new(f));
+    }
+
+    #[cfg(feature = "tray")]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop (or restart) picking up waiting items, the running one goes on
+    pub fn set_paused(queue: &Rc<RefCell<Self>>, paused: bool) {
+        queue.borrow_mut().paused = paused;
+        Self::run_next(queue);
+    }
+
+    fn index_of(&self, id: usize) -> Option<usize> {
+        self.items.iter().position(|item| item.id == id)
+    }
+
+    fn item(&mut self, id: usize) -> Option<&mut QueueItem> {
+        self.items.iter_mut().find(|item| item.id == id)
+    }
+
+    /// Append `url` to the queue and start it if nothing else is running
+    pub fn add(queue: &Rc<RefCell<Self>>, url: String) {
+        Self::add_with(queue, url, HashMap::new());
+    }
+
+    /// Like `add`, with `overrides` replacing config values for this URL only
+    pub fn add_with(queue: &Rc<RefCell<Self>>, url: String, overrides: HashMap<String, String>) {
+        let id = {
+            let mut q = queue.borrow_mut();
+            q.next_id += 1;
+            q.next_id
+        };
+
+        let bar = gtk::ProgressBar::new();
+        bar.set_show_text(true);
+        bar.set_hexpand(true);
+
+        let up_btn = gtk::Button::from_icon_name(Some("go-up-symbolic"), gtk::IconSize::Button);
+        let down_btn = gtk::Button::from_icon_name(Some("go-down-symbolic"), gtk::IconSize::Button);
+        let cancel_btn = gtk::Button::from_icon_name(Some("process-stop-symbolic"), gtk::IconSize::Button);
+        let retry_btn = gtk::Button::from_icon_name(Some("view-refresh-symbolic"), gtk::IconSize::Button);
+        let open_btn = gtk::Button::from_icon_name(Some("document-open-symbolic"), gtk::IconSize::Button);
+
+        let weak = Rc::downgrade(queue);
+        up_btn.connect_clicked(move |_| {
+            if let Some(queue) = weak.upgrade() { Self::move_item(&queue, id, -1); }
+        });
+        let weak = Rc::downgrade(queue);
+        down_btn.connect_clicked(move |_| {
+            if let Some(queue) = weak.upgrade() { Self::move_item(&queue, id, 1); }
+        });
+        let weak = Rc::downgrade(queue);
+        cancel_btn.connect_clicked(move |_| {
+            if let Some(queue) = weak.upgrade() { Self::cancel(&queue, id); }
+        });
+        let weak = Rc::downgrade(queue);
+        retry_btn.connect_clicked(move |_| {
+            if let Some(queue) = weak.upgrade() { Self::retry(&queue, id); }
+        });
+        let weak = Rc::downgrade(queue);
+        open_btn.connect_clicked(move |_| {
+            if let Some(queue) = weak.upgrade() {
+                let path = queue.borrow_mut().item(id).and_then(|item| item.path.clone());
+                if let Some(path) = path { open_file(&path); }
+            }
+        });
+
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        hbox.add(&bar);
+        hbox.add(&up_btn);
+        hbox.add(&down_btn);
+        hbox.add(&cancel_btn);
+        hbox.add(&retry_btn);
+        hbox.add(&open_btn);
+
+        let row = gtk::ListBoxRow::new();
+        row.add(&hbox);
+
+        let mut item = QueueItem {
+            id,
+            attempt: 0,
+            url,
+            state: JobState::Waiting,
+            row,
+            bar,
+            cancel_btn,
+            retry_btn,
+            open_btn,
+            path: None,
+            stage: String::new(),
+            overrides,
+        };
+        item.set_state(JobState::Waiting, tr("waiting"));
+
+        {
+            let mut q = queue.borrow_mut();
+            q.list.add(&item.row);
+            item.row.show_all();
+            q.items.push(item);
+        }
+
+        Self::run_next(queue);
+    }
+
+    /// Cancel the waiting or extracting item `id`
+    fn cancel(queue: &Rc<RefCell<Self>>, id: usize) {
+        {
+            let mut q = queue.borrow_mut();
+            if let Some(item) = q.item(id) {
+                if !matches!(item.state, JobState::Waiting | JobState::Downloading) {
+                    return
+                }
+                item.set_state(JobState::Cancelled, tr("cancelled"));
+            }
+            if q.running == Some(id) {
+                q.running = None;  // Its extraction is discarded when it comes back
+            }
+        }
+
+        Self::run_next(queue);
+    }
+
+    /// Cancel the item being converted, if it can still be cancelled
+    pub fn cancel_current(queue: &Rc<RefCell<Self>>) {
+        let running = queue.borrow().running;
+        if let Some(id) = running {
+            Self::cancel(queue, id);
+        }
+    }
+
+    fn retry(queue: &Rc<RefCell<Self>>, id: usize) {
+        if let Some(item) = queue.borrow_mut().item(id) {
+            item.bar.set_fraction(0.0);
+            item.set_state(JobState::Waiting, tr("waiting"));
+        }
+
+        Self::run_next(queue);
+    }
+
+    /// Move item `id` up (negative `offset`) or down the queue
+    fn move_item(queue: &Rc<RefCell<Self>>, id: usize, offset: i32) {
+        let mut q = queue.borrow_mut();
+        let index = match q.index_of(id) {
+            Some(index) => index as i32,
+            None => return,
+        };
+
+        let new_index = index + offset;
+        if new_index < 0 || new_index >= q.items.len() as i32 {
+            return
+        }
+
+        let item = q.items.remove(index as usize);
+        q.list.remove(&item.row);
+        q.list.insert(&item.row, new_index);
+        q.items.insert(new_index as usize, item);
+    }
+
+    fn run_next(queue: &Rc<RefCell<Self>>) {
+        let next = {
+            let q = queue.borrow();
+            if q.running.is_some() || q.paused {
+                return
+            }
+            q.items.iter().find(|item| item.state == JobState::Waiting).map(|item| item.id)
+        };
+
+        if let Some(id) = next {
+            Self::start(queue, id);
+        }
+    }
+
+    fn finish(queue: &Rc<RefCell<Self>>, id: usize) {
+        {
+            let mut q = queue.borrow_mut();
+            if q.running == Some(id) {
+                q.running = None;
+            }
+        }
+
+        Self::run_next(queue);
+    }
+
+    fn start(queue: &Rc<RefCell<Self>>, id: usize) {
+        let (url, attempt, cfg, http_mode, win) = {
+            let mut q = queue.borrow_mut();
+            q.running = Some(id);
+            // Config is reloaded for every conversion, so saved settings apply right away
+            let (mut cfg, http_mode, win) = (PultConf::reload(), q.http_mode.clone(), q.win.clone());
+
+            let item = q.item(id).unwrap();
+            cfg.extend(item.overrides.clone());
+            item.attempt += 1;
+            item.set_state(JobState::Downloading, &Progress::Fetch.label());
+            (item.url.clone(), item.attempt, cfg, http_mode, win)
+        };
+
+        // Messages from the conversion threads
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let build_sender = sender.clone();
+        let build_cfg = cfg.clone();
+        let weak = Rc::downgrade(queue);
+        receiver.attach(None, move |msg: ConvMsg| {
+            let queue = match weak.upgrade() {
+                Some(queue) => queue,
+                None => return glib::Continue(false),
+            };
+
+            let stale = match queue.borrow_mut().item(id) {
+                Some(item) => item.attempt != attempt || item.state == JobState::Cancelled,
+                None => true,
+            };
+
+            match msg {
+                ConvMsg::Step(p) => {
+                    if !stale {
+                        if let Some(item) = queue.borrow_mut().item(id) {
+                            item.bar.set_fraction(p.fraction());
+                            let label = p.label();
+                            item.set_state(JobState::from_progress(&p), &label);
+                            item.stage = label;
+                        }
+                    }
+
+                    let over = matches!(p, Progress::Done | Progress::Failed(_));
+                    if over && !stale {
+                        Self::finish(&queue, id);
+                    }
+                    glib::Continue(!over)
+                },
+                ConvMsg::Extracted(mut extraction) => {
+                    // No borrow is held while the dialog runs, so the queue stays usable
+                    if stale || !confirm_preview(&win, &mut extraction) {
+                        let _ = extraction.discard();
+                        if !stale {
+                            if let Some(item) = queue.borrow_mut().item(id) {
+                                item.set_state(JobState::Cancelled, tr("cancelled"));
+                            }
+                            Self::finish(&queue, id);
+                        }
+                        return glib::Continue(false)
+                    }
+
+                    let sender = build_sender.clone();
+                    let cfg = build_cfg.clone();
+                    thread::spawn(move || {
+                        let progress = |p: Progress| { let _ = sender.send(ConvMsg::Step(p)); };
+                        let title = extraction.article.title().to_string();
+                        let msg = match catch_unwind(AssertUnwindSafe(|| extraction.build(&cfg, &progress))) {
+                            Ok(Ok(path)) => ConvMsg::Saved(title, path),
+                            Ok(Err(e)) => ConvMsg::Failed(e.to_string(), explain_error(&e)),
+                            Err(panic) => crashed(panic),
+                        };
+                        let _ = sender.send(msg);
+                    });
+                    glib::Continue(true)
+                },
+                ConvMsg::Saved(title, path) => {
+                    if stale {
+                        return glib::Continue(false)
+                    }
+
+                    {
+                        let mut q = queue.borrow_mut();
+                        if let Some(item) = q.item(id) {
+                            item.bar.set_fraction(Progress::Done.fraction());
+                            item.path = Some(path.clone());
+                            item.set_state(JobState::Delivered, &Progress::Done.label());
+                            History::record(&item.url, &title, path.clone());
+                        }
+                        q.recent.insert(0, (title.clone(), path.clone()));
+                    }
+
+                    // Handlers may use the queue, so it's not borrowed while they run
+                    let handlers = std::mem::take(&mut queue.borrow_mut().saved_handlers);
+                    for handler in &handlers {
+                        handler(&title, &path);
+                    }
+                    queue.borrow_mut().saved_handlers = handlers;
+
+                    Self::finish(&queue, id);
+                    glib::Continue(false)
+                },
+                ConvMsg::Failed(message, explanation) => {
+                    if stale {
+                        return glib::Continue(false)
+                    }
+
+                    let step = Progress::Failed(message.clone());
+                    let (url, stage) = match queue.borrow_mut().item(id) {
+                        Some(item) => {
+                            item.bar.set_fraction(step.fraction());
+                            item.set_state(JobState::Failed, &step.label());
+                            (item.url.clone(), item.stage.clone())
+                        },
+                        None => return glib::Continue(false),
+                    };
+                    Self::finish(&queue, id);
+
+                    // The queue goes on while the dialog is open
+                    let failure = Failure { url, stage, message, explanation };
+                    match run_failure_dialog(&win, &failure) {
+                        FailureAction::Retry => Self::retry(&queue, id),
+                        FailureAction::RetryWithoutImages => {
+                            if let Some(item) = queue.borrow_mut().item(id) {
+                                item.overrides.insert("skip_images".into(), "true".into());
+                            }
+                            Self::retry(&queue, id);
+                        },
+                        FailureAction::Close => {},
+                    }
+                    glib::Continue(false)
+                },
+            }
+        });
+
+        thread::spawn(move || {
+            let progress = |p: Progress| { let _ = sender.send(ConvMsg::Step(p)); };
+            let msg = match catch_unwind(AssertUnwindSafe(|| Article::extract(url, &cfg, http_mode, &progress))) {
+                Ok(Ok(Some(extraction))) => ConvMsg::Extracted(Box::new(extraction)),
+                Ok(Ok(None)) => ConvMsg::Failed("invalid URL".into(), tr("error-invalid-url")),
+                Ok(Err(e)) => ConvMsg::Failed(e.to_string(), explain_error(&e)),
+                Err(panic) => crashed(panic),
+            };
+            let _ = sender.send(msg);
+        });
+    }
+}
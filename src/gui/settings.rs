@@ -0,0 +1,182 @@
+use gtk::prelude::*;
+
+use crate::config::{PultConf, DEVICE_PROFILES, OUT_FORMATS};
+use crate::delivery::TARGETS;
+use crate::i18n::tr;
+use crate::web::THEMES;
+use super::CfgField;
+
+const PARSERS: &[&str] = &["mozilla", "python"];
+
+/// Combo box listing `choices`, with `active` selected
+pub fn choice_combo(choices: &[&str], active: &str) -> gtk::ComboBoxText {
+    let combo = gtk::ComboBoxText::new();
+    for choice in choices {
+        combo.append(Some(choice), choice);
+    }
+    combo.set_active_id(Some(active));
+    combo
+}
+
+/// Stored config to save changes over, `None` after telling the user it can't be read:
+/// storing defaults in its place would lose every setting in it
+pub fn load_conf<W: IsA<gtk::Window>>(parent: &W) -> Option<PultConf> {
+    match confy::load("kindle-pult") {
+        Ok(conf) => Some(conf),
+        Err(e) => {
+            println!("Can't load settings: {}", e);
+            let dialog = gtk::MessageDialog::new(
+                Some(parent),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Close,
+                tr("settings-unreadable"),
+            );
+            dialog.set_property_secondary_text(Some(&e.to_string()));
+            dialog.run();
+            dialog.close();
+            None
+        },
+    }
+}
+
+/// Settings dialog, reading and writing the same config file the CLI uses.
+/// Returns whether settings were saved.
+pub fn run_settings_dialog(win: &gtk::ApplicationWindow) -> bool {
+    let cfg = PultConf::reload();
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(tr("settings")),
+        Some(win),
+        gtk::DialogFlags::MODAL,
+        &[(tr("save"), gtk::ResponseType::Ok), (tr("cancel"), gtk::ResponseType::Cancel)]
+    );
+
+    // Email delivery
+    let from_mail = CfgField::new(tr("from-label"), cfg.get("from_mail").unwrap());
+    let to_mail = CfgField::new(tr("to-label"), cfg.get("to_mail").unwrap());
+    let smtp = CfgField::new(tr("protocol-label"), cfg.get("smtp").unwrap());
+    let port = CfgField::new(tr("port-label"), cfg.get("port").unwrap());
+    let user = CfgField::new(tr("user-label"), cfg.get("username").unwrap());
+    let password = CfgField::new(tr("password-label"), cfg.get("password").unwrap());
+    let to_ext = CfgField::new(tr("extension-label"), cfg.get("to_ext").unwrap());
+    password.entry.set_visibility(false);
+
+    let del_sent = gtk::Switch::new();
+    let del_sent_lbl = gtk::Label::new(Some(tr("delete-sents")));
+    del_sent.set_active(cfg.get("del_sent").unwrap().parse().unwrap_or(false));
+    del_sent.set_halign(gtk::Align::Start);
+
+    // Conversion
+    let out_dir_lbl = gtk::Label::new(Some(tr("output-folder-label")));
+    let out_dir = gtk::FileChooserButton::new(tr("output-folder"), gtk::FileChooserAction::SelectFolder);
+    let current_dir = cfg.get("out_dir").unwrap();
+    if !current_dir.is_empty() {
+        out_dir.set_filename(current_dir);
+    }
+
+    let device_names: Vec<&str> = DEVICE_PROFILES.iter().map(|(name, _)| *name).collect();
+    let device_lbl = gtk::Label::new(Some(tr("device-label")));
+    let device = choice_combo(&device_names, cfg.get("device").unwrap());
+    let parser_lbl = gtk::Label::new(Some(tr("parser-label")));
+    let parser = choice_combo(PARSERS, cfg.get("parser").unwrap());
+    let theme_lbl = gtk::Label::new(Some(tr("theme-label")));
+    let theme = choice_combo(THEMES, cfg.get("css_theme").unwrap());
+    let out_format_lbl = gtk::Label::new(Some(tr("format-label")));
+    let out_format = choice_combo(OUT_FORMATS, cfg.get("out_format").unwrap());
+
+    let delivery_lbl = gtk::Label::new(Some(tr("deliver-to-label")));
+    let delivery = choice_combo(TARGETS, cfg.get("delivery").unwrap());
+    let device_dir_lbl = gtk::Label::new(Some(tr("e-reader-folder-label")));
+    let device_dir = gtk::FileChooserButton::new(tr("e-reader-folder"), gtk::FileChooserAction::SelectFolder);
+    let current_device_dir = cfg.get("device_dir").unwrap();
+    if !current_device_dir.is_empty() {
+        device_dir.set_filename(current_device_dir);
+    }
+
+    let cloud_url = CfgField::new(tr("cloud-folder-label"), cfg.get("cloud_url").unwrap());
+    cloud_url.entry.set_placeholder_text(Some("https://cloud.example.com/remote.php/dav/files/user/Books"));
+    let cloud_user = CfgField::new(tr("cloud-user-label"), cfg.get("cloud_username").unwrap());
+    let cloud_password = CfgField::new(tr("cloud-password-label"), cfg.get("cloud_password").unwrap());
+    cloud_password.entry.set_visibility(false);
+
+    let watch_clipboard = gtk::Switch::new();
+    let watch_clipboard_lbl = gtk::Label::new(Some(tr("watch-clipboard")));
+    watch_clipboard.set_active(cfg.get("watch_clipboard").unwrap().parse().unwrap_or(false));
+    watch_clipboard.set_halign(gtk::Align::Start);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(10);
+    grid.set_column_spacing(10);
+
+    // Up to two label/widget pairs per row
+    let rows: &[&[(&gtk::Label, &gtk::Widget)]] = &[
+        &[(&from_mail.label, from_mail.entry.upcast_ref()), (&to_mail.label, to_mail.entry.upcast_ref())],
+        &[(&smtp.label, smtp.entry.upcast_ref()), (&port.label, port.entry.upcast_ref())],
+        &[(&user.label, user.entry.upcast_ref()), (&password.label, password.entry.upcast_ref())],
+        &[(&to_ext.label, to_ext.entry.upcast_ref()), (&del_sent_lbl, del_sent.upcast_ref())],
+        &[(&out_dir_lbl, out_dir.upcast_ref()), (&device_lbl, device.upcast_ref())],
+        &[(&parser_lbl, parser.upcast_ref()), (&theme_lbl, theme.upcast_ref())],
+        &[(&out_format_lbl, out_format.upcast_ref()), (&watch_clipboard_lbl, watch_clipboard.upcast_ref())],
+        &[(&delivery_lbl, delivery.upcast_ref()), (&device_dir_lbl, device_dir.upcast_ref())],
+        &[(&cloud_url.label, cloud_url.entry.upcast_ref())],
+        &[(&cloud_user.label, cloud_user.entry.upcast_ref()), (&cloud_password.label, cloud_password.entry.upcast_ref())],
+    ];
+    for (row, pairs) in rows.iter().enumerate() {
+        for (col, (label, widget)) in pairs.iter().enumerate() {
+            grid.attach(*label, col as i32 * 2, row as i32, 1, 1);
+            grid.attach(*widget, col as i32 * 2 + 1, row as i32, 1, 1);
+        }
+    }
+
+    let content = dialog.get_content_area();
+    content.set_margin_top(10);
+    content.set_margin_start(10);
+    content.set_margin_end(10);
+    content.set_margin_bottom(10);
+    content.add(&grid);
+
+    dialog.show_all();
+    let response = dialog.run();
+
+    // Start from the stored config, so options without a widget are kept
+    let stored = match response {
+        gtk::ResponseType::Ok => load_conf(&dialog),
+        _ => None,
+    };
+    let saved = stored.is_some();
+    if let Some(mut conf) = stored {
+
+        conf.del_sent = del_sent.get_active().to_string();
+        conf.to_ext = to_ext.buffer.get_text();
+        conf.smtp = smtp.buffer.get_text();
+        conf.port = port.buffer.get_text();
+        conf.username = user.buffer.get_text();
+        conf.password = password.buffer.get_text();
+        conf.from_mail = from_mail.buffer.get_text();
+        conf.to_mail = to_mail.buffer.get_text();
+        conf.out_dir = out_dir.get_filename()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        conf.device = device.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+        conf.parser = parser.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+        conf.css_theme = theme.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+        conf.out_format = out_format.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+        conf.watch_clipboard = watch_clipboard.get_active().to_string();
+        conf.delivery = delivery.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+        conf.device_dir = device_dir.get_filename()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        conf.cloud_url = cloud_url.buffer.get_text();
+        conf.cloud_username = cloud_user.buffer.get_text();
+        conf.cloud_password = cloud_password.buffer.get_text();
+
+        if let Err(e) = confy::store("kindle-pult", conf) {
+            println!("Can't save settings: {:?}", e);
+        }
+    }
+
+    dialog.close();
+    saved
+}
+
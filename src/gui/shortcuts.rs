@@ -0,0 +1,155 @@
+use gtk::prelude::*;
+use gio::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use crate::config::PultConf;
+use crate::i18n::tr;
+use super::inputs::open_input;
+use super::queue::ConvQueue;
+use super::settings::run_settings_dialog;
+
+/// Window actions: name, message ID of the label shown in the palette and keyboard shortcut.
+/// The palette itself is last, so palette rows and actions share indexes.
+const ACTIONS: &[(&str, &str, &str)] = &[
+    ("paste-convert", "convert-clipboard-url", "<Primary><Shift>v"),
+    ("settings", "settings", "<Primary>comma"),
+    ("cancel-current", "cancel-current-conversion", "<Primary>period"),
+    ("focus-queue", "focus-queue", "<Primary>j"),
+    ("palette", "command-palette", "<Primary><Shift>p"),
+];
+
+/// Let the user pick an action by typing part of its name, returns the picked action
+fn run_palette(win: &gtk::ApplicationWindow) -> Option<&'static str> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(tr("command-palette")),
+        Some(win),
+        gtk::DialogFlags::MODAL,
+        &[]
+    );
+
+    let search = gtk::SearchEntry::new();
+    let list = gtk::ListBox::new();
+    for (_, label, accel) in ACTIONS.iter().filter(|(name, _, _)| *name != "palette") {
+        let (key, mods) = gtk::accelerator_parse(accel);
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 20);
+        let label = gtk::Label::new(Some(tr(label)));
+        label.set_hexpand(true);
+        label.set_halign(gtk::Align::Start);
+        hbox.add(&label);
+        let accel_label = gtk::accelerator_get_label(key, mods).map(|l| l.to_string()).unwrap_or_default();
+        hbox.add(&gtk::Label::new(Some(&accel_label)));
+        list.add(&hbox);
+    }
+
+    // Actions whose label contains the typed text, in list order
+    let query = Rc::new(RefCell::new(String::new()));
+    let matches = |query: &str| -> Vec<&'static str> {
+        ACTIONS.iter()
+            .filter(|(name, label, _)| *name != "palette" && tr(label).to_lowercase().contains(query))
+            .map(|(name, _, _)| *name)
+            .collect()
+    };
+
+    let query_clone = Rc::clone(&query);
+    list.set_filter_func(Some(Box::new(move |row| {
+        let label = ACTIONS[row.get_index() as usize].1;
+        tr(label).to_lowercase().contains(query_clone.borrow().as_str())
+    })));
+
+    let query_clone = Rc::clone(&query);
+    let list_clone = list.clone();
+    search.connect_search_changed(move |search| {
+        *query_clone.borrow_mut() = search.get_text().to_lowercase();
+        list_clone.invalidate_filter();
+    });
+
+    let picked = Rc::new(Cell::new(None));
+
+    let picked_clone = Rc::clone(&picked);
+    let dialog_clone = dialog.clone();
+    search.connect_activate(move |search| {
+        picked_clone.set(matches(&search.get_text().to_lowercase()).first().copied());
+        dialog_clone.response(gtk::ResponseType::Ok);
+    });
+
+    let picked_clone = Rc::clone(&picked);
+    let dialog_clone = dialog.clone();
+    list.connect_row_activated(move |_, row| {
+        picked_clone.set(Some(ACTIONS[row.get_index() as usize].0));
+        dialog_clone.response(gtk::ResponseType::Ok);
+    });
+
+    let content = dialog.get_content_area();
+    content.set_spacing(10);
+    content.set_margin_top(10);
+    content.set_margin_start(10);
+    content.set_margin_end(10);
+    content.set_margin_bottom(10);
+    content.add(&search);
+    content.add(&list);
+
+    dialog.show_all();
+    dialog.run();
+    dialog.close();
+
+    picked.get()
+}
+
+/// Register the window actions and their shortcuts
+pub fn build_actions(
+    win: &gtk::ApplicationWindow,
+    queue: &Rc<RefCell<ConvQueue>>,
+    source_files: &Arc<Mutex<Vec<PathBuf>>>,
+    watch_clipboard: &Rc<Cell<bool>>,
+) {
+    let paste_convert = gio::SimpleAction::new("paste-convert", None);
+    let (queue_clone, source_files) = (Rc::clone(queue), Arc::clone(source_files));
+    paste_convert.connect_activate(move |_, _| {
+        let (queue, source_files) = (Rc::clone(&queue_clone), Arc::clone(&source_files));
+        gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).request_text(move |_, text| {
+            match text {
+                Some(text) => open_input(text, &queue, &source_files),
+                None => println!("Nothing to convert in clipboard."),
+            }
+        });
+    });
+
+    let settings = gio::SimpleAction::new("settings", None);
+    let watch_clipboard = Rc::clone(watch_clipboard);
+    settings.connect_activate(glib::clone!(@weak win => move |_, _| {
+        if run_settings_dialog(&win) {
+            watch_clipboard.set(PultConf::reload().get("watch_clipboard").unwrap().parse().unwrap_or(false));
+        }
+    }));
+
+    let cancel_current = gio::SimpleAction::new("cancel-current", None);
+    let queue_clone = Rc::clone(queue);
+    cancel_current.connect_activate(move |_, _| ConvQueue::cancel_current(&queue_clone));
+
+    let focus_queue = gio::SimpleAction::new("focus-queue", None);
+    let queue_clone = Rc::clone(queue);
+    focus_queue.connect_activate(move |_, _| {
+        queue_clone.borrow().widget().child_focus(gtk::DirectionType::TabForward);
+    });
+
+    let palette = gio::SimpleAction::new("palette", None);
+    palette.connect_activate(glib::clone!(@weak win => move |_, _| {
+        if let Some(action) = run_palette(&win) {
+            win.activate_action(action, None);
+        }
+    }));
+
+    for action in &[paste_convert, settings, cancel_current, focus_queue, palette] {
+        win.add_action(action);
+    }
+
+    if let Some(app) = win.get_application() {
+        for (name, _, accel) in ACTIONS {
+            app.set_accels_for_action(&format!("win.{}", name), &[accel]);
+        }
+    }
+}
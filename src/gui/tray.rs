@@ -0,0 +1,108 @@
+use gtk::prelude::*;
+use gio::prelude::*;
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+
+use libappindicator::{AppIndicator, AppIndicatorStatus};
+use url::Url;
+
+use crate::i18n::tr;
+use super::open_file;
+use super::queue::ConvQueue;
+
+const MAX_RECENT: usize = 10;
+
+/// Fill `menu` with the latest saved EPUBs, opened on activation
+fn fill_recent(menu: &gtk::Menu, recent: &[(String, PathBuf)]) {
+    for child in menu.get_children() {
+        menu.remove(&child);
+    }
+
+    if recent.is_empty() {
+        let empty = gtk::MenuItem::with_label(tr("nothing-yet"));
+        empty.set_sensitive(false);
+        menu.append(&empty);
+    }
+
+    for (title, path) in recent.iter().take(MAX_RECENT) {
+        let item = gtk::MenuItem::with_label(title);
+        let path = path.clone();
+        item.connect_activate(move |_| open_file(&path));
+        menu.append(&item);
+    }
+
+    menu.show_all();
+}
+
+/// Tray icon with quick actions; closing the window hides it in the tray
+pub fn build_tray(win: &gtk::ApplicationWindow, queue: &Rc<RefCell<ConvQueue>>) {
+    let menu = gtk::Menu::new();
+
+    let show_item = gtk::MenuItem::with_label(tr("show-window"));
+    show_item.connect_activate(glib::clone!(@weak win => move |_| win.present()));
+
+    // Queue a copied URL
+    let clipboard_item = gtk::MenuItem::with_label(tr("convert-clipboard-url"));
+    let weak: Weak<RefCell<ConvQueue>> = Rc::downgrade(queue);
+    clipboard_item.connect_activate(move |_| {
+        let weak = weak.clone();
+        gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).request_text(move |_, text| {
+            let url = match text.map(|t| Url::parse(t.trim())) {
+                Some(Ok(url)) => url,
+                _ => { println!("No URL in clipboard."); return },
+            };
+            if let Some(queue) = weak.upgrade() {
+                ConvQueue::add(&queue, url.to_string());
+            }
+        });
+    });
+
+    // Recent conversions, refreshed every time an EPUB is saved
+    let recent_item = gtk::MenuItem::with_label(tr("recent-conversions"));
+    let recent_menu = gtk::Menu::new();
+    fill_recent(&recent_menu, queue.borrow().recent());
+    recent_item.set_submenu(Some(&recent_menu));
+
+    let weak: Weak<RefCell<ConvQueue>> = Rc::downgrade(queue);
+    queue.borrow_mut().connect_saved(move |_, _| {
+        if let Some(queue) = weak.upgrade() {
+            fill_recent(&recent_menu, queue.borrow().recent());
+        }
+    });
+
+    let pause_item = gtk::CheckMenuItem::with_label(tr("pause-queue"));
+    pause_item.set_active(queue.borrow().is_paused());
+    let weak: Weak<RefCell<ConvQueue>> = Rc::downgrade(queue);
+    pause_item.connect_toggled(move |item| {
+        if let Some(queue) = weak.upgrade() {
+            ConvQueue::set_paused(&queue, item.get_active());
+        }
+    });
+
+    let quit_item = gtk::MenuItem::with_label(tr("quit"));
+    quit_item.connect_activate(glib::clone!(@weak win => move |_| {
+        if let Some(app) = win.get_application() {
+            app.quit();
+        }
+    }));
+
+    menu.append(&show_item);
+    menu.append(&clipboard_item);
+    menu.append(&recent_item);
+    menu.append(&pause_item);
+    menu.append(&gtk::SeparatorMenuItem::new());
+    menu.append(&quit_item);
+    menu.show_all();
+
+    // Keep running in the tray when the window is closed
+    win.connect_delete_event(|win, _| {
+        win.hide();
+        Inhibit(true)
+    });
+
+    let mut indicator = AppIndicator::new("Kindle-pult", "document-send");
+    indicator.set_status(AppIndicatorStatus::Active);
+    indicator.set_menu(&mut menu.clone());  // The indicator object stays alive with the app
+}
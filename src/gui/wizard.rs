@@ -0,0 +1,193 @@
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+use url::Url;
+
+use crate::config::{PultConf, DEVICE_PROFILES};
+use crate::desktop::register_handlers;
+use crate::i18n::tr;
+use crate::paths;
+use super::queue::ConvQueue;
+use super::settings::{choice_combo, load_conf};
+use super::CfgField;
+
+/// Short article converted at the end of the wizard, to check the whole pipeline works
+const SAMPLE_ARTICLE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Welcome to Kindle-pult</title></head>
+<body>
+<article>
+<h1>Welcome to Kindle-pult</h1>
+<p class="byline">Kindle-pult</p>
+<p>If you are reading this on your e-reader, Kindle-pult is set up: web pages pasted in the
+URL field are turned into clean books, with their images resized for your device.</p>
+<p>Files chosen with the open button are converted with Calibre and mailed to your Kindle
+address when you press Send.</p>
+<p>Settings can be changed at any time from the settings button in the title bar.</p>
+</article>
+</body>
+</html>
+"#;
+
+/// Page of the assistant: a grid of label/widget rows under an explanation
+fn wizard_page(intro: &str, rows: &[(&gtk::Label, &gtk::Widget)]) -> gtk::Box {
+    let page = gtk::Box::new(gtk::Orientation::Vertical, 10);
+
+    let intro = gtk::Label::new(Some(intro));
+    intro.set_line_wrap(true);
+    intro.set_max_width_chars(60);
+    intro.set_halign(gtk::Align::Start);
+    page.add(&intro);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(10);
+    grid.set_column_spacing(10);
+    for (row, (label, widget)) in rows.iter().enumerate() {
+        grid.attach(*label, 0, row as i32, 1, 1);
+        grid.attach(*widget, 1, row as i32, 1, 1);
+    }
+    page.add(&grid);
+
+    page
+}
+
+/// Queue the sample article, written to the cache dir
+fn convert_sample(queue: &Rc<RefCell<ConvQueue>>) {
+    let path = paths::cache_dir().join("kindle-pult-welcome.html");
+    if let Err(e) = fs::write(&path, SAMPLE_ARTICLE) {
+        println!("Can't write sample article: {}", e);
+        return
+    }
+    if let Ok(url) = Url::from_file_path(&path) {
+        ConvQueue::add(queue, url.to_string());
+    }
+}
+
+/// First-run assistant for the essential settings: device, email delivery, output folder
+/// and a sample conversion
+pub fn run_setup_wizard(win: &gtk::ApplicationWindow, queue: &Rc<RefCell<ConvQueue>>) {
+    let cfg = PultConf::reload();
+
+    let assistant = gtk::Assistant::new();
+    assistant.set_transient_for(Some(win));
+    assistant.set_modal(true);
+    assistant.set_title(tr("set-up-kindle-pult"));
+
+    // Welcome
+    let welcome = gtk::Label::new(Some(tr("wizard-welcome")));
+    welcome.set_line_wrap(true);
+    welcome.set_max_width_chars(60);
+    assistant.append_page(&welcome);
+    assistant.set_page_type(&welcome, gtk::AssistantPageType::Intro);
+    assistant.set_page_title(&welcome, tr("welcome"));
+    assistant.set_page_complete(&welcome, true);
+
+    // Device
+    let device_names: Vec<&str> = DEVICE_PROFILES.iter().map(|(name, _)| *name).collect();
+    let device_lbl = gtk::Label::new(Some(tr("device-label")));
+    let device = choice_combo(&device_names, cfg.get("device").unwrap());
+    let device_page = wizard_page(
+        tr("wizard-device-question"),
+        &[(&device_lbl, device.upcast_ref())],
+    );
+    assistant.append_page(&device_page);
+    assistant.set_page_title(&device_page, tr("device"));
+    assistant.set_page_complete(&device_page, true);
+
+    // Email delivery
+    let to_mail = CfgField::new(tr("kindle-address-label"), cfg.get("to_mail").unwrap());
+    let from_mail = CfgField::new(tr("from-label"), cfg.get("from_mail").unwrap());
+    let smtp = CfgField::new(tr("protocol-label"), cfg.get("smtp").unwrap());
+    let port = CfgField::new(tr("port-label"), cfg.get("port").unwrap());
+    let user = CfgField::new(tr("user-label"), cfg.get("username").unwrap());
+    let password = CfgField::new(tr("password-label"), cfg.get("password").unwrap());
+    password.entry.set_visibility(false);
+    let mail_page = wizard_page(
+        tr("wizard-email-help"),
+        &[
+            (&to_mail.label, to_mail.entry.upcast_ref()),
+            (&from_mail.label, from_mail.entry.upcast_ref()),
+            (&smtp.label, smtp.entry.upcast_ref()),
+            (&port.label, port.entry.upcast_ref()),
+            (&user.label, user.entry.upcast_ref()),
+            (&password.label, password.entry.upcast_ref()),
+        ],
+    );
+    assistant.append_page(&mail_page);
+    assistant.set_page_title(&mail_page, tr("email"));
+    assistant.set_page_complete(&mail_page, true);
+
+    // Output folder
+    let out_dir_lbl = gtk::Label::new(Some(tr("output-folder-label")));
+    let out_dir = gtk::FileChooserButton::new(tr("output-folder"), gtk::FileChooserAction::SelectFolder);
+    let current_dir = cfg.get("out_dir").unwrap();
+    if !current_dir.is_empty() {
+        out_dir.set_filename(current_dir);
+    }
+    let folder_page = wizard_page(
+        tr("wizard-folder-question"),
+        &[(&out_dir_lbl, out_dir.upcast_ref())],
+    );
+    assistant.append_page(&folder_page);
+    assistant.set_page_title(&folder_page, tr("output-folder"));
+    assistant.set_page_complete(&folder_page, true);
+
+    // Sample conversion
+    let sample = gtk::CheckButton::with_label(tr("wizard-sample"));
+    sample.set_active(true);
+    let sample_page = wizard_page(tr("wizard-all-set"), &[]);
+    sample_page.add(&sample);
+    assistant.append_page(&sample_page);
+    assistant.set_page_type(&sample_page, gtk::AssistantPageType::Confirm);
+    assistant.set_page_title(&sample_page, tr("done"));
+    assistant.set_page_complete(&sample_page, true);
+
+    let queue = Rc::clone(queue);
+    assistant.connect_apply(move |assistant| {
+        // Start from the stored config, so options without a widget are kept
+        if let Some(mut conf) = load_conf(assistant) {
+            conf.device = device.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+            conf.to_mail = to_mail.buffer.get_text();
+            conf.from_mail = from_mail.buffer.get_text();
+            conf.smtp = smtp.buffer.get_text();
+            conf.port = port.buffer.get_text();
+            conf.username = user.buffer.get_text();
+            conf.password = password.buffer.get_text();
+            conf.out_dir = out_dir.get_filename()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .unwrap_or_default();
+            conf.setup_done = "true".into();
+
+            if let Err(e) = confy::store("kindle-pult", conf) {
+                println!("Can't save settings: {:?}", e);
+            }
+        }
+
+        // Let browsers and other apps hand links and files over
+        if let Err(e) = register_handlers() {
+            println!("Can't register kindle-pult:// links: {}", e);
+        }
+
+        if sample.get_active() {
+            convert_sample(&queue);
+        }
+    });
+
+    // Dismissing the wizard counts as done too, the settings dialog covers the same options.
+    // `close()` would raise delete-event, which emits cancel again: the wizard is hidden.
+    assistant.connect_cancel(|assistant| {
+        if let Some(mut conf) = load_conf(assistant) {
+            conf.setup_done = "true".into();
+            if let Err(e) = confy::store("kindle-pult", conf) {
+                println!("Can't save settings: {:?}", e);
+            }
+        }
+        assistant.hide();
+    });
+    assistant.connect_close(|assistant| assistant.hide());
+
+    assistant.show_all();
+}
@@ -0,0 +1,43 @@
+use serde::{Serialize, Deserialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Past conversions, stored in the data dir
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    pub path: PathBuf,  // Saved EPUB
+    pub timestamp: u64,  // Seconds since the Unix epoch
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,  // Newest first
+}
+
+impl History {
+    fn path() -> PathBuf {
+        crate::paths::data_dir().join("history.toml")
+    }
+
+    /// Saved history, empty if there is none yet
+    pub fn load() -> Self {
+        confy::load_path(Self::path()).unwrap_or_default()
+    }
+
+    /// Add a conversion on top of the saved history
+    pub fn record(url: &str, title: &str, path: PathBuf) {
+        let mut history = Self::load();
+        history.entries.insert(0, HistoryEntry {
+            url: url.to_string(),
+            title: title.to_string(),
+            path,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        });
+
+        if let Err(e) = confy::store_path(Self::path(), history) {
+            println!("Can't save history: {}", e);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::{Client, ClientBuilder, Response};
+use reqwest::redirect::Policy;
+use reqwest::{header, Certificate};
+use url::Url;
+
+use crate::web::errors::*;
+
+/// Redirects followed for a single request
+const MAX_REDIRECTS: usize = 10;
+
+/// HTTP client shared by the downloads of a conversion, set up from the network options
+pub struct HttpClient {
+    client: Client,
+    insecure: Option<Client>,  // Skips certificate checks, only for `insecure_hosts`
+    insecure_hosts: Vec<String>,
+    certificates: Vec<Certificate>,  // Extra trusted CAs
+    // Name resolution bypassing the system DNS, for broken or censoring networks
+    dns_overrides: HashMap<String, IpAddr>,
+    doh_url: Option<Url>,  // DNS-over-HTTPS resolver, JSON API
+    resolved: RefCell<HashMap<String, Client>>,  // Clients pinned to the address of a host
+    rate_limit: Option<RefCell<RateLimit>>,  // Download rate cap, none when unset
+}
+
+/// Download rate cap as a token bucket: downloaded bytes are spent and earned back at `rate`
+/// per second, up to one second's worth, so idle time can't buy a long burst
+struct RateLimit {
+    rate: f64,  // Bytes per second
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimit {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self { rate: rate as f64, tokens: rate as f64, last: now }
+    }
+
+    /// How long to wait, having downloaded `bytes` at `now`, to stay under the rate
+    fn spend(&mut self, bytes: usize, now: Instant) -> Duration {
+        let earned = now.saturating_duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + earned).min(self.rate) - bytes as f64;
+        self.last = now;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Reader sleeping as needed to keep its client under the download rate cap
+pub struct Throttled<'a, R> {
+    inner: R,
+    http: &'a HttpClient,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Small reads keep the pace smooth
+        let len = buf.len().min(16 * 1024);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.http.pace(read);
+        Ok(read)
+    }
+}
+
+impl HttpClient {
+    pub fn from_cfg(cfg: &HashMap<String, String>) -> Result<Self> {
+        let option = |key: &str| cfg.get(key).map(|value| value.trim()).unwrap_or_default();
+
+        // Extra CA bundle, e.g. of a corporate proxy or a self-hosted service
+        let mut certificates = Vec::new();
+        if !option("tls_ca_file").is_empty() {
+            let bundle = fs::read_to_string(option("tls_ca_file"))?;
+            for pem in bundle.split_inclusive("-----END CERTIFICATE-----").filter(|pem| pem.contains("-----BEGIN")) {
+                certificates.push(Certificate::from_pem(pem.trim().as_bytes())?);
+            }
+        }
+
+        let insecure_hosts = option("tls_insecure_hosts")
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect::<Vec<_>>();
+
+        // host=address pairs, comma separated
+        let mut dns_overrides = HashMap::new();
+        for pair in option("dns_overrides").split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (host, ip) = match pair.split_once('=') {
+                Some((host, ip)) => (host.trim().to_lowercase(), ip.trim()),
+                None => bail!("DNS override '{}' is not in the host=address form", pair.trim()),
+            };
+            let ip = ip.parse().map_err(|_| format!("'{}' is not an IP address", ip))?;
+            dns_overrides.insert(host, ip);
+        }
+
+        let doh_url = match option("dns_over_https") {
+            "" => None,
+            url => Some(Url::parse(url).map_err(|e| format!("Invalid DNS-over-HTTPS URL '{}': {}", url, e))?),
+        };
+
+        let mut http = Self {
+            client: Client::new(),
+            insecure: None,
+            insecure_hosts,
+            certificates,
+            dns_overrides,
+            doh_url,
+            resolved: RefCell::new(HashMap::new()),
+            rate_limit: match option("max_download_rate").parse::<u64>().unwrap_or(0) {
+                0 => None,
+                rate => Some(RefCell::new(RateLimit::new(rate * 1024, Instant::now()))),  // Set in KiB/s
+            },
+        };
+        http.client = http.builder(false).build()?;
+        if !http.insecure_hosts.is_empty() {
+            http.insecure = Some(http.builder(true).build()?);
+        }
+
+        Ok(http)
+    }
+
+    /// Client builder with the TLS options. Redirects are followed by `get`, each hop with the
+    /// client of its own host.
+    fn builder(&self, insecure: bool) -> ClientBuilder {
+        let mut builder = Client::builder().danger_accept_invalid_certs(insecure).redirect(Policy::none());
+        for certificate in &self.certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder
+    }
+
+    /// Account for `bytes` just downloaded, sleeping if they came in faster than the cap
+    pub fn pace(&self, bytes: usize) {
+        if let Some(rate_limit) = &self.rate_limit {
+            let wait = rate_limit.borrow_mut().spend(bytes, Instant::now());
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+    }
+
+    /// `reader` of a response, kept under the download rate cap
+    pub fn throttle<R: Read>(&self, reader: R) -> Throttled<'_, R> {
+        Throttled { inner: reader, http: self }
+    }
+
+    /// Address of `host` from the DNS-over-HTTPS resolver, A records first
+    fn doh_lookup(&self, doh_url: &Url, host: &str) -> Result<IpAddr> {
+        for record_type in &["A", "AAAA"] {
+            let mut query = doh_url.clone();
+            query.query_pairs_mut().append_pair("name", host).append_pair("type", record_type);
+            let answer: serde_json::Value = serde_json::from_str(
+                &self.client.get(query.as_str()).header("accept", "application/dns-json").send()?.text()?
+            )?;
+
+            // Answers may start with CNAMEs, the address records follow them
+            let ip = answer["Answer"].as_array().into_iter().flatten()
+                .filter_map(|record| record["data"].as_str())
+                .find_map(|data| data.parse::<IpAddr>().ok());
+            if let Some(ip) = ip {
+                return Ok(ip)
+            }
+        }
+
+        bail!("DNS-over-HTTPS lookup of '{}' found no address", host)
+    }
+
+    /// GET `url` following its redirects. Every hop is resolved and has its certificate
+    /// checked on its own: a listed host can't send the download to an unchecked one.
+    pub fn get(&self, url: &Url) -> Result<Response> {
+        let mut url = url.clone();
+        for _ in 0..MAX_REDIRECTS {
+            let response = self.get_once(&url)?;
+            let location = response.headers().get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok());
+            match location {
+                Some(next) if response.status().is_redirection() => {
+                    println!("'{}' redirects to '{}'", url, next);
+                    url = next;
+                },
+                _ => return Ok(response),
+            }
+        }
+
+        bail!("'{}' redirects too many times", url)
+    }
+
+    /// GET `url` without following redirects
+    fn get_once(&self, url: &Url) -> Result<Response> {
+        Ok(self.client_for(url)?.get(url.as_str()).send()?)
+    }
+
+    /// PUT `body` at `url`, with HTTP basic auth when a `username` is given, e.g. to upload
+    /// a book into a WebDAV folder. Redirects are not followed, the body would be lost.
+    pub fn put(&self, url: &Url, body: Vec<u8>, username: &str, password: &str) -> Result<Response> {
+        let mut request = self.client_for(url)?.put(url.as_str()).body(body);
+        if !username.is_empty() {
+            request = request.basic_auth(username, Some(password));
+        }
+        Ok(request.send()?)
+    }
+
+    /// Client for requests to `url`: pinned to the address of its host when it is resolved
+    /// here, without checking certificates only if its host was explicitly listed
+    fn client_for(&self, url: &Url) -> Result<Client> {
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        let insecure = self.insecure_hosts.contains(&host);
+
+        // IP addresses and local files need no resolution
+        let resolve = url.domain().is_some() && (self.doh_url.is_some() || self.dns_overrides.contains_key(&host));
+        if resolve {
+            if !self.resolved.borrow().contains_key(&host) {
+                let ip = match (self.dns_overrides.get(&host), &self.doh_url) {
+                    (Some(ip), _) => *ip,
+                    (None, Some(doh_url)) => self.doh_lookup(doh_url, &host)?,
+                    (None, None) => unreachable!(),
+                };
+                println!("'{}' resolved to {}", host, ip);
+                // The port is ignored, the URL one is used
+                let client = self.builder(insecure).resolve(&host, SocketAddr::new(ip, 0)).build()?;
+                self.resolved.borrow_mut().insert(host.clone(), client);
+            }
+            return Ok(self.resolved.borrow()[&host].clone())
+        }
+
+        Ok(match &self.insecure {
+            Some(insecure_client) if insecure => insecure_client.clone(),
+            _ => self.client.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_waits_for_bytes_over_the_rate() {
+        let start = Instant::now();
+        let mut limit = RateLimit::new(1000, start);
+        assert_eq!(limit.spend(500, start), Duration::ZERO);
+        assert_eq!(limit.spend(1000, start), Duration::from_millis(500));
+        // Waiting that long brings the bucket back to empty
+        assert_eq!(limit.spend(1000, start + Duration::from_millis(500)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn idle_time_buys_one_second_of_burst_at_most() {
+        let start = Instant::now();
+        let mut limit = RateLimit::new(1000, start);
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limit.spend(1000, later), Duration::ZERO);
+        assert_eq!(limit.spend(2000, later), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+// GUI and generated page translations, as Fluent messages in `locales/<language>/kindle-pult.ftl`.
+// Messages missing in the user language are shown in English.
+
+/// Bundled languages, English is the source one
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en/kindle-pult.ftl")),
+    ("it", include_str!("../locales/it/kindle-pult.ftl")),
+];
+
+/// User language from the usual locale variables, e.g. "it" for `it_IT.UTF-8`
+fn language() -> String {
+    ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| value.chars().take(2).collect::<String>().to_lowercase())
+        .unwrap_or_else(|| "en".into())
+}
+
+/// Bundle of the Fluent `source` of language `code`
+fn bundle(code: &str, source: &str) -> FluentBundle<FluentResource> {
+    let lang: LanguageIdentifier = code.parse().unwrap_or_default();
+    let mut bundle = FluentBundle::new_concurrent(vec![lang]);
+    bundle.set_use_isolating(false);  // GTK shows the bidi isolation marks around variables
+
+    // A broken entry only loses its own message
+    let resource = FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, errors)| {
+        println!("Errors in the {} translation: {:?}", code, errors);
+        resource
+    });
+    if let Err(errors) = bundle.add_resource(resource) {
+        println!("Errors in the {} translation: {:?}", code, errors);
+    }
+    bundle
+}
+
+/// Bundles to look messages up in: the user language, then English
+fn bundles() -> &'static [FluentBundle<FluentResource>] {
+    static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+    BUNDLES.get_or_init(|| {
+        let mut codes = vec![language(), "en".to_string()];
+        codes.dedup();
+        codes.iter()
+            .filter_map(|code| LOCALES.iter().find(|(bundled, _)| bundled == code))
+            .map(|(code, source)| bundle(code, source))
+            .collect()
+    })
+}
+
+/// Message `id` of the first bundle having it, the id itself if none does
+fn format(bundles: &[FluentBundle<FluentResource>], id: &str, args: Option<&FluentArgs>) -> String {
+    for bundle in bundles {
+        if let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) {
+            let mut errors = vec![];
+            return bundle.format_pattern(pattern, args, &mut errors).into_owned();
+        }
+    }
+    id.to_string()
+}
+
+/// Message `id` in the user language.
+/// Each message is formatted once and kept for the whole run, as widgets and errors hold on to it.
+pub fn tr(id: &'static str) -> &'static str {
+    static MESSAGES: OnceLock<Mutex<HashMap<&'static str, &'static str>>> = OnceLock::new();
+    let mut messages = MESSAGES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    messages.entry(id).or_insert_with(|| Box::leak(format(bundles(), id, None).into_boxed_str()))
+}
+
+/// Message `id` in the user language with its numeric variables, e.g. `count` picking the plural form
+pub fn tr_args(id: &str, args: &[(&'static str, usize)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, FluentValue::from(*value));
+    }
+    format(bundles(), id, Some(&fluent_args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ids of the messages in the Fluent `source`, which must parse
+    fn ids(source: &str) -> Vec<&str> {
+        assert!(FluentResource::try_new(source.to_string()).is_ok(), "translation doesn't parse");
+        source.lines()
+            .filter(|line| !line.starts_with(' ') && !line.starts_with('#'))
+            .filter_map(|line| line.split_once(" =").map(|(id, _)| id))
+            .collect()
+    }
+
+    #[test]
+    fn translations_have_the_english_messages() {
+        let english = ids(LOCALES[0].1);
+        for (code, source) in LOCALES {
+            let translated = ids(source);
+            let missing: Vec<_> = english.iter().filter(|id| !translated.contains(id)).collect();
+            let unknown: Vec<_> = translated.iter().filter(|id| !english.contains(id)).collect();
+            assert!(missing.is_empty() && unknown.is_empty(), "{}: missing {:?}, unknown {:?}", code, missing, unknown);
+        }
+    }
+
+    #[test]
+    fn plurals_follow_the_language() {
+        let english = [bundle("en", LOCALES[0].1)];
+        let italian = [bundle("it", LOCALES[1].1), bundle("en", LOCALES[0].1)];
+        let count = |n: usize| {
+            let mut args = FluentArgs::new();
+            args.set("count", FluentValue::from(n));
+            args
+        };
+
+        assert_eq!(format(&english, "images-detected", Some(&count(1))), "1 image detected, untick it to leave it out");
+        assert_eq!(format(&english, "images-detected", Some(&count(3))), "3 images detected, untick the ones to leave out");
+        assert_eq!(format(&italian, "images-detected", Some(&count(1))), "1 immagine trovata, deselezionala per escluderla");
+        assert_eq!(format(&italian, "images-detected", Some(&count(0))), "0 immagini trovate, deseleziona quelle da escludere");
+    }
+
+    #[test]
+    fn missing_messages_fall_back_to_english_then_the_id() {
+        let italian = [bundle("it", "notes = Note\n"), bundle("en", LOCALES[0].1)];
+        assert_eq!(format(&italian, "notes", None), "Note");
+        assert_eq!(format(&italian, "source", None), "Source");
+        assert_eq!(format(&italian, "no-such-message", None), "no-such-message");
+    }
+}
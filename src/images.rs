@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::fs;
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+extern crate image;
+use image::io::Reader as ImageReader;
+use image::codecs::jpeg::JpegDecoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+
+extern crate exif;
+
+use crate::config::device_width;
+use crate::web::EpubImage;
+use crate::web::errors::*;
+
+/// Image media types supported in EPUBs and the extension used when saving them
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/bmp", "bmp"),
+    ("image/svg+xml", "svg"),
+];
+
+/// Media type of an encoded image, from its first bytes
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if head.starts_with(b"BM") {
+        Some("image/bmp")
+    } else if String::from_utf8_lossy(head).contains("<svg") {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+/// Media type of the image at `path`: sniffed from its content, then taken from the
+/// `Content-Type` it was served with, then guessed from its extension
+pub fn detect_mime(path: &Path, content_type: Option<&str>) -> Option<&'static str> {
+    let mut head = Vec::with_capacity(512);
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.take(512).read_to_end(&mut head);
+    }
+    let from_header = content_type
+        .map(|ct| ct.split(';').next().unwrap_or_default().trim().to_lowercase())
+        .and_then(|ct| IMAGE_TYPES.iter().find(|(mime, _)| *mime == ct).map(|(mime, _)| *mime));
+    let from_ext = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .map(|ext| if ext == "jpeg" { "jpg".to_string() } else { ext })
+        .and_then(|ext| IMAGE_TYPES.iter().find(|(_, e)| *e == ext).map(|(mime, _)| *mime));
+
+    sniff_mime(&head).or(from_header).or(from_ext)
+}
+
+/// Give the downloaded image at `path` the extension of its actual type, returns its path.
+/// Image URLs often have no extension, a wrong one or a query string in its place.
+pub fn fix_extension(path: &Path, content_type: Option<&str>) -> Result<PathBuf> {
+    let ext = match detect_mime(path, content_type) {
+        Some(mime) => IMAGE_TYPES.iter().find(|(m, _)| *m == mime).map(|(_, ext)| *ext).unwrap(),
+        None => return Ok(path.to_path_buf()),  // Unknown type, leave it be
+    };
+    let current = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if current == ext || (ext == "jpg" && current == "jpeg") {
+        return Ok(path.to_path_buf())
+    }
+
+    let fixed = path.with_extension(ext);
+    fs::rename(path, &fixed)?;
+    Ok(fixed)
+}
+
+/// Rename the image at `path` after a hash of its content, returns its new path.
+/// Different images sharing a name (`image.jpg`, `tmp.bin`) can't overwrite each other.
+pub fn content_addressed(path: &Path) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&fs::read(path)?);
+
+    let mut name = format!("img-{:016x}", hasher.finish());
+    if let Some(ext) = path.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    let addressed = path.with_file_name(name);
+    fs::rename(path, &addressed)?;
+
+    Ok(addressed)
+}
+
+/// EXIF orientation of the image at `path`, 1 (upright) when it has none
+fn exif_orientation(path: &Path) -> u32 {
+    fs::File::open(path).ok()
+        .and_then(|file| exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file)).ok())
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+/// `img` turned upright according to its EXIF `orientation`, which many readers ignore
+fn orient(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// JPEG `bytes` without the metadata segments (EXIF with its GPS data, XMP, IPTC,
+/// comments), pixels are left untouched. Colour profiles and Adobe markers are kept.
+pub fn strip_jpeg_metadata(bytes: &[u8]) -> Vec<u8> {
+    const APP1: u8 = 0xE1;  // EXIF and XMP
+    const APP13: u8 = 0xED;  // IPTC
+    const COM: u8 = 0xFE;
+    const SOS: u8 = 0xDA;  // Start of the compressed data, no more segments after it
+
+    let mut stripped = Vec::with_capacity(bytes.len());
+    stripped.extend_from_slice(&bytes[..2.min(bytes.len())]);  // SOI
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        if marker == SOS {
+            break
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = (pos + 2 + len).min(bytes.len());
+        if marker != APP1 && marker != APP13 && marker != COM {
+            stripped.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+    stripped.extend_from_slice(&bytes[pos.min(bytes.len())..]);
+
+    stripped
+}
+
+/// PNG `bytes` without the metadata chunks (text, EXIF, modification time), pixels and
+/// colour information are left untouched. Malformed files are returned as they are.
+pub fn strip_png_metadata(bytes: &[u8]) -> Vec<u8> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const METADATA: &[&[u8]] = &[b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+    if !bytes.starts_with(SIGNATURE) {
+        return bytes.to_vec()
+    }
+
+    let mut stripped = Vec::with_capacity(bytes.len());
+    stripped.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    while pos < bytes.len() {
+        // Length, type, data and CRC
+        let end = match bytes.get(pos..pos + 4) {
+            Some(len) => pos + 12 + u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize,
+            None => return bytes.to_vec(),
+        };
+        if end > bytes.len() {
+            return bytes.to_vec()
+        }
+        if !METADATA.contains(&&bytes[pos + 4..pos + 8]) {
+            stripped.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+
+    stripped
+}
+
+/// WebP `bytes` without the EXIF and XMP chunks, and without their flags in the extended
+/// header. Malformed files are returned as they are.
+pub fn strip_webp_metadata(bytes: &[u8]) -> Vec<u8> {
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+    if bytes.len() < 12 || !bytes.starts_with(b"RIFF") || &bytes[8..12] != b"WEBP" {
+        return bytes.to_vec()
+    }
+
+    let mut stripped = Vec::with_capacity(bytes.len());
+    stripped.extend_from_slice(&bytes[..12]);
+    let mut pos = 12;
+    while pos < bytes.len() {
+        // FourCC, size and data, padded to an even size
+        let size = match bytes.get(pos + 4..pos + 8) {
+            Some(size) => u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize,
+            None => return bytes.to_vec(),
+        };
+        let end = pos + 8 + size + size % 2;
+        if end > bytes.len() {
+            return bytes.to_vec()
+        }
+        match &bytes[pos..pos + 4] {
+            b"EXIF" | b"XMP " => {},
+            b"VP8X" if size > 0 => {
+                stripped.extend_from_slice(&bytes[pos..end]);
+                let flags = stripped.len() - (end - pos) + 8;
+                stripped[flags] &= !(EXIF_FLAG | XMP_FLAG);
+            },
+            _ => stripped.extend_from_slice(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+
+    let riff_size = (stripped.len() - 8) as u32;
+    stripped[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    stripped
+}
+
+/// Transforms applied to article images before embedding them
+pub struct ImageOpts {
+    pub max_width: u32,  // 0 means no downscaling
+    pub greyscale: bool,
+    pub min_size: u32,  // Images smaller on both sides are decorative, 0 keeps all
+    pub min_bytes: u64,
+}
+
+impl ImageOpts {
+    pub fn from_cfg(cfg: &HashMap<String, String>) -> Self {
+        Self {
+            max_width: match cfg.get("img_max_width").and_then(|w| w.parse().ok()).unwrap_or(0) {
+                0 => device_width(cfg.get("device").map(String::as_str).unwrap_or_default()),
+                width => width,
+            },
+            greyscale: cfg.get("img_greyscale").and_then(|g| g.parse().ok()).unwrap_or(false),
+            min_size: cfg.get("img_min_size").and_then(|s| s.parse().ok()).unwrap_or(0),
+            min_bytes: cfg.get("img_min_bytes").and_then(|b| b.parse().ok()).unwrap_or(0),
+        }
+    }
+
+    /// Whether the image at `path` is a tracking pixel, spacer or icon, not worth embedding.
+    /// Images whose size can't be read (e.g. SVG) are only judged by their file size.
+    pub fn is_decorative(&self, path: &Path) -> bool {
+        let bytes = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+        if bytes < self.min_bytes {
+            return true
+        }
+
+        let dimensions = ImageReader::open(path).ok()
+            .and_then(|reader| reader.with_guessed_format().ok())
+            .and_then(|reader| reader.into_dimensions().ok());
+        matches!(dimensions, Some((width, height)) if width < self.min_size && height < self.min_size)
+    }
+
+    fn needs_transform(&self, width: u32) -> bool {
+        self.greyscale || (self.max_width > 0 && width > self.max_width)
+    }
+
+    /// Prepare the image at `path` for the EPUB.
+    /// Only the header is read to check dimensions: the image is fully decoded only when a
+    /// transform applies, otherwise the original encoded file is streamed into the book.
+    /// Sideways photos are turned upright and the metadata of JPEG, PNG and WebP images is
+    /// always stripped.
+    pub fn process(&self, path: &Path) -> Result<EpubImage> {
+        let filename = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let mime = detect_mime(path, None).unwrap_or("application/octet-stream").to_string();
+
+        let reader = ImageReader::open(path)?.with_guessed_format()?;
+        // Unknown formats and broken headers can't be transformed anyway, they go in as they are
+        let (format, width) = match reader.format().map(|format| (format, reader.into_dimensions())) {
+            Some((format, Ok((width, _height)))) => (Some(format), width),
+            Some((_, Err(e))) => {
+                println!("Can't read the size of {:?}, embedding it as it is: {}", path, e);
+                (None, 0)
+            },
+            None => (None, 0),
+        };
+
+        let orientation = match format {
+            Some(_) => exif_orientation(path),
+            None => 1,
+        };
+
+        if format.is_none() || (!self.needs_transform(width) && orientation == 1) {
+            // Told by the sniffed type, so images the decoder can't read (lossless, alpha or
+            // animated WebP, broken headers) lose their metadata too
+            let data: Box<dyn Read + Send> = match mime.as_str() {
+                "image/jpeg" => Box::new(Cursor::new(strip_jpeg_metadata(&fs::read(path)?))),
+                "image/png" => Box::new(Cursor::new(strip_png_metadata(&fs::read(path)?))),
+                "image/webp" => Box::new(Cursor::new(strip_webp_metadata(&fs::read(path)?))),
+                _ => Box::new(fs::File::open(path)?),
+            };
+            return Ok(EpubImage { filename, data, mime })
+        }
+
+        // Re-encoding drops the metadata too
+        let mut img = orient(ImageReader::open(path)?.with_guessed_format()?.decode()?, orientation);
+        let width = img.width();
+        if self.max_width > 0 && width > self.max_width {
+            img = img.resize(self.max_width, u32::MAX, FilterType::Triangle);
+        }
+        if self.greyscale {
+            img = img.grayscale();
+        }
+
+        // Re-encode in the original format when possible, PNG otherwise
+        let (out_format, filename, mime) = match format {
+            Some(ImageFormat::Jpeg) => (ImageOutputFormat::Jpeg(85), filename, mime),
+            Some(ImageFormat::Png) => (ImageOutputFormat::Png, filename, mime),
+            Some(ImageFormat::Gif) => (ImageOutputFormat::Gif, filename, mime),
+            _ => {
+                let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+                (ImageOutputFormat::Png, format!("{}.png", stem), "image/png".to_string())
+            },
+        };
+
+        let mut bytes = Vec::new();
+        img.write_to(&mut bytes, out_format)?;
+
+        Ok(EpubImage { filename, data: Box::new(Cursor::new(bytes)), mime })
+    }
+}
+
+/// Largest image decoded for a thumbnail, in pixels: about 200 MB once decoded
+const MAX_THUMBNAIL_PIXELS: u64 = 50_000_000;
+
+/// Save a preview of the image at `path`, fitting in `size` pixels, next to it.
+/// JPEGs are decoded at a reduced scale, other images only if they are not huge.
+pub fn thumbnail(path: &Path, size: u32) -> Result<PathBuf> {
+    let thumb_path = path.with_extension("thumb.png");
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    let img = match reader.format() {
+        Some(ImageFormat::Jpeg) => {
+            let mut decoder = JpegDecoder::new(BufReader::new(fs::File::open(path)?))?;
+            let side = size.min(u16::MAX.into()) as u16;
+            decoder.scale(side, side)?;
+            DynamicImage::from_decoder(decoder)?
+        },
+        _ => {
+            let (width, height) = ImageReader::open(path)?.with_guessed_format()?.into_dimensions()?;
+            if width as u64 * height as u64 > MAX_THUMBNAIL_PIXELS {
+                bail!("{:?} is too big for a thumbnail ({}x{})", path, width, height);
+            }
+            reader.decode()?
+        },
+    };
+    img.thumbnail(size, size).save_with_format(&thumb_path, ImageFormat::Png)?;
+
+    Ok(thumb_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PNG chunk with a dummy CRC, which stripping doesn't check
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn webp_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        file.extend_from_slice(b"WEBP");
+        file.extend_from_slice(&body);
+        file
+    }
+
+    #[test]
+    fn jpeg_metadata_segments_are_dropped() {
+        let jpeg = [
+            &[0xFF, 0xD8][..],  // SOI
+            &[0xFF, 0xE0, 0x00, 0x04, b'J', b'F'],  // APP0 (JFIF), kept
+            &[0xFF, 0xE1, 0x00, 0x06, b'E', b'x', b'i', b'f'],  // APP1 (EXIF)
+            &[0xFF, 0xFE, 0x00, 0x03, b'c'],  // Comment
+            &[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9],  // Scan and EOI
+        ].concat();
+        let expected = [
+            &[0xFF, 0xD8][..],
+            &[0xFF, 0xE0, 0x00, 0x04, b'J', b'F'],
+            &[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9],
+        ].concat();
+        assert_eq!(strip_jpeg_metadata(&jpeg), expected);
+    }
+
+    #[test]
+    fn jpeg_without_metadata_is_unchanged() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9];
+        assert_eq!(strip_jpeg_metadata(&jpeg), jpeg);
+        assert_eq!(strip_jpeg_metadata(&[0xFF]), [0xFF]);
+    }
+
+    #[test]
+    fn png_metadata_chunks_are_dropped() {
+        let ihdr = png_chunk(b"IHDR", &[0; 13]);
+        let idat = png_chunk(b"IDAT", &[1, 2, 3]);
+        let iend = png_chunk(b"IEND", &[]);
+        let png = [
+            b"\x89PNG\r\n\x1a\n".to_vec(), ihdr.clone(), png_chunk(b"tEXt", b"Author\0me"),
+            png_chunk(b"eXIf", b"MM\0*"), idat.clone(), iend.clone(),
+        ].concat();
+        let expected = [b"\x89PNG\r\n\x1a\n".to_vec(), ihdr, idat, iend].concat();
+        assert_eq!(strip_png_metadata(&png), expected);
+    }
+
+    #[test]
+    fn truncated_png_is_unchanged() {
+        let png = [b"\x89PNG\r\n\x1a\n".to_vec(), png_chunk(b"tEXt", b"abc")[..9].to_vec()].concat();
+        assert_eq!(strip_png_metadata(&png), png);
+    }
+
+    #[test]
+    fn webp_metadata_chunks_and_flags_are_dropped() {
+        let vp8 = webp_chunk(b"VP8 ", &[1, 2, 3]);
+        let with_metadata = webp(&[
+            webp_chunk(b"VP8X", &[0x0C, 0, 0, 0, 0, 0, 0, 0, 0, 0]), vp8.clone(),
+            webp_chunk(b"EXIF", b"MM\0*"), webp_chunk(b"XMP ", b"<x/>"),
+        ]);
+        let expected = webp(&[webp_chunk(b"VP8X", &[0; 10]), vp8]);
+        assert_eq!(strip_webp_metadata(&with_metadata), expected);
+    }
+
+    #[test]
+    fn lossless_webp_is_stripped_though_it_cant_be_decoded() {
+        let vp8l = webp_chunk(b"VP8L", &[0x2F, 0x01, 0x40, 0x00, 0x00, 0x07]);
+        let with_metadata = webp(&[
+            webp_chunk(b"VP8X", &[0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0]), vp8l.clone(),
+            webp_chunk(b"EXIF", b"MM\0*GPS"),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lossless.webp");
+        fs::write(&path, &with_metadata).unwrap();
+
+        let opts = ImageOpts { max_width: 0, greyscale: false, min_size: 0, min_bytes: 0 };
+        let mut embedded = Vec::new();
+        opts.process(&path).unwrap().data.read_to_end(&mut embedded).unwrap();
+        assert_eq!(embedded, webp(&[webp_chunk(b"VP8X", &[0; 10]), vp8l]));
+    }
+
+    #[test]
+    fn thumbnails_fit_the_size() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in &["photo.jpg", "drawing.png"] {
+            let path = dir.path().join(name);
+            DynamicImage::new_rgb8(800, 400).save(&path).unwrap();
+            let thumb = image::open(thumbnail(&path, 96).unwrap()).unwrap();
+            assert_eq!(thumb.dimensions(), (96, 48), "{}", name);
+        }
+    }
+
+    #[test]
+    fn non_webp_is_unchanged() {
+        assert_eq!(strip_webp_metadata(b"RIFF\0\0\0\0WAVE"), b"RIFF\0\0\0\0WAVE");
+    }
+}
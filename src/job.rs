@@ -0,0 +1,68 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Batch job state, persisted after every file so an interrupted run can be resumed
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BatchJob {
+    pub pending: Vec<PathBuf>,
+    pub done: Vec<PathBuf>,
+    pub failed: Vec<PathBuf>,
+}
+
+impl BatchJob {
+    fn state_path() -> PathBuf {
+        crate::paths::data_dir().join("job.json")
+    }
+
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Self {
+            pending: files,
+            ..Default::default()
+        }
+    }
+
+    /// Load the last saved job, if any
+    pub fn load() -> Option<Self> {
+        let json = fs::read_to_string(Self::state_path()).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = fs::write(Self::state_path(), json) {
+                    println!("Can't save job state: {}", e);
+                }
+            },
+            Err(e) => println!("Can't serialize job state: {}", e),
+        }
+    }
+
+    /// Files still to process: the pending ones and the failed ones, to retry them
+    pub fn remaining(&self) -> Vec<PathBuf> {
+        self.pending.iter().chain(self.failed.iter()).cloned().collect()
+    }
+
+    pub fn mark_done(&mut self, file: &Path) {
+        self.pending.retain(|f| f.as_path() != file);
+        self.failed.retain(|f| f.as_path() != file);
+        self.done.push(file.to_path_buf());
+        self.save();
+    }
+
+    pub fn mark_failed(&mut self, file: &Path) {
+        self.pending.retain(|f| f.as_path() != file);
+        if !self.failed.iter().any(|f| f.as_path() == file) {
+            self.failed.push(file.to_path_buf());
+        }
+        self.save();
+    }
+
+    /// Forget the saved state once everything went through
+    pub fn finish(&self) {
+        if self.pending.is_empty() && self.failed.is_empty() {
+            let _ = fs::remove_file(Self::state_path());
+        }
+    }
+}
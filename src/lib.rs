@@ -0,0 +1,35 @@
+//! Conversion core of kindle-pult: extraction, image processing, EPUB building and delivery.
+//! The GTK application and the daemon are built on top of it, in the binary; C programs use
+//! it through `ffi`, Python ones through the `python` feature.
+//!
+//! On wasm32 only the parts without file system, network or process access are compiled:
+//! `Article::from_json`, the HTML helpers and `build_epub`.
+
+#[macro_use]
+extern crate error_chain;
+
+pub mod config;
+pub mod i18n;
+pub mod web;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cmd;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod delivery;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod history;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod http;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod images;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod job;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mail;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod paths;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod report;
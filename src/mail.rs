@@ -0,0 +1,39 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mailparse::{parse_mail, MailHeaderMap, ParsedMail};
+
+use crate::web::errors::*;
+use crate::web::escape_xml;
+
+/// First part of `mail` with the given MIME type, depth first
+fn find_part<'a>(mail: &'a ParsedMail<'a>, mimetype: &str) -> Option<&'a ParsedMail<'a>> {
+    if mail.ctype.mimetype == mimetype {
+        return Some(mail)
+    }
+    mail.subparts.iter().find_map(|part| find_part(part, mimetype))
+}
+
+/// Save the body of the mail at `path` as an HTML page in `out_dir`, ready to be extracted
+/// like any other article. Plain text mails are wrapped in a minimal page.
+pub fn html_from_eml(path: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let raw = fs::read(path)?;
+    let mail = parse_mail(&raw).map_err(|e| e.to_string())?;
+    let subject = mail.headers.get_first_value("Subject").unwrap_or_default();
+
+    let html = match (find_part(&mail, "text/html"), find_part(&mail, "text/plain")) {
+        (Some(part), _) => part.get_body().map_err(|e| e.to_string())?,
+        (None, Some(part)) => format!(
+            "<html><head><title>{0}</title></head><body><h1>{0}</h1><pre>{1}</pre></body></html>",
+            escape_xml(&subject),
+            escape_xml(&part.get_body().map_err(|e| e.to_string())?),
+        ),
+        (None, None) => bail!("No readable body in {:?}", path),
+    };
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("mail");
+    let out_path = out_dir.join(format!("{}.html", stem));
+    fs::write(&out_path, html)?;
+
+    Ok(out_path)
+}
@@ -2,26 +2,104 @@ extern crate glib;
 extern crate gio;
 extern crate gtk;
 use gio::prelude::*;
+use glib::clone;
 use std::env::args;
 
-#[macro_use]
-extern crate error_chain;
-
 mod gui;
-mod web;
-mod cmd;
-mod config;
+mod daemon;
+mod desktop;
+
+// The conversion core lives in the library, GUI and daemon use it through the same paths
+use kindle_pult::{config, delivery, history, i18n, job, mail, paths, web};
 
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::daemon::Daemon;
 use crate::gui::Gui;
+use crate::job::BatchJob;
+use crate::web::HttpMode;
+
+/// Take `--record <dir>` or `--replay <dir>` out of args, GTK would reject them.
+fn http_mode_from_args(args: &mut Vec<String>) -> HttpMode {
+    let mut mode = HttpMode::Live;
+
+    if let Some(i) = args.iter().position(|a| a == "--record" || a == "--replay") {
+        if i + 1 < args.len() {
+            let dir = PathBuf::from(args.remove(i + 1));
+            mode = if args[i] == "--record" { HttpMode::Record(dir) } else { HttpMode::Replay(dir) };
+        }
+        args.remove(i);
+    }
+
+    mode
+}
+
+/// The application window, built on first use so that both a plain launch and files opened
+/// through the desktop (e.g. dropped on the dock icon) end up in the same one.
+fn main_gui(app: &gtk::Application, slot: &RefCell<Option<Rc<Gui>>>, http_mode: &HttpMode, resume: bool) -> Rc<Gui> {
+    if let Some(gui) = &*slot.borrow() {
+        return Rc::clone(gui)
+    }
+
+    let gui = Rc::new(Gui::new(app, http_mode.clone()));
+    if resume {
+        match BatchJob::load() {
+            Some(job) => gui.queue_files(job.remaining()),
+            None => println!("No job to resume."),
+        }
+    }
+    gui.build();
+    *slot.borrow_mut() = Some(Rc::clone(&gui));
+    gui
+}
 
 fn main() {
+    let mut args = args().collect::<Vec<_>>();
+    let http_mode = http_mode_from_args(&mut args);
+
+    // Daemon subcommands, no GUI involved
+    match args.get(1).map(String::as_str) {
+        Some("daemon") => {
+            if let Err(e) = Daemon::run(http_mode) { println!("Daemon failed: {}", e); }
+            return
+        },
+        Some("add") => {
+            for url in &args[2..] {
+                match Daemon::send(&format!("add {}", url)) {
+                    Ok(reply) => println!("{}", reply),
+                    Err(e) => println!("Is the daemon running? {}", e),
+                }
+            }
+            return
+        },
+        Some("register") => {
+            match desktop::register_handlers() {
+                Ok(()) => println!("Registered kindle-pult:// links and \"Open with\" entries."),
+                Err(e) => println!("Registration failed: {}", e),
+            }
+            return
+        },
+        _ => {},
+    }
+
     if gtk::init().is_err() { println!("Failed to initialize GTK."); return; }
-    let application = gtk::Application::new(Some("kindle-pult.zwitterio.it"), Default::default())
+    let application = gtk::Application::new(Some("kindle-pult.zwitterio.it"), gio::ApplicationFlags::HANDLES_OPEN)
     .expect("Initialization failed...");
 
-    application.connect_activate(move |app| {
-        let gui = Gui::new(app);
-        gui.build();
+    let resume = match args.iter().position(|a| a == "--resume") {
+        Some(i) => { args.remove(i); true },
+        None => false,
+    };
+
+    let gui = Rc::new(RefCell::new(None));
+    application.connect_activate(clone!(@strong gui, @strong http_mode => move |app| {
+        main_gui(app, &gui, &http_mode, resume);
+    }));
+    application.connect_open(move |app, files, _hint| {
+        let inputs = files.iter().map(|f| f.get_uri().to_string()).collect::<Vec<_>>();
+        main_gui(app, &gui, &http_mode, resume).open_inputs(&inputs);
     });
-    application.run(&args().collect::<Vec<_>>());
+    application.run(&args);
 }
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use directories::{ProjectDirs, UserDirs};
+
+use crate::web::errors::*;
+
+// Platform directories: XDG ones on Linux, `AppData` on Windows, `Library` on macOS.
+// The project name matches confy's, so the config file sits next to the other data.
+
+fn project() -> Option<ProjectDirs> {
+    ProjectDirs::from("rs", "", "kindle-pult")
+}
+
+/// `dir`, created if missing
+fn created(dir: PathBuf) -> PathBuf {
+    if let Err(e) = fs::create_dir_all(&dir) {
+        println!("Can't create {:?}: {}", dir, e);
+    }
+    dir
+}
+
+/// Disposable files: downloads and extraction artifacts
+pub fn cache_dir() -> PathBuf {
+    created(project().map(|dirs| dirs.cache_dir().to_path_buf()).unwrap_or_else(env::temp_dir))
+}
+
+/// Files to keep across runs: history, interrupted jobs
+pub fn data_dir() -> PathBuf {
+    created(project().map(|dirs| dirs.data_dir().to_path_buf()).unwrap_or_else(env::temp_dir))
+}
+
+/// Files living as long as the session, like sockets: the XDG runtime dir, or a private
+/// folder in the data dir where there is none. Never the shared temp dir, where other users
+/// could reach them.
+pub fn runtime_dir() -> io::Result<PathBuf> {
+    let dirs = project().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    if let Some(dir) = dirs.runtime_dir() {
+        fs::create_dir_all(dir)?;
+        return Ok(dir.to_path_buf())
+    }
+
+    let dir = dirs.data_dir().join("run");
+    fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    Ok(dir)
+}
+
+/// Where books go when no output folder is set: "Kindle-pult" in the user documents
+pub fn default_out_dir() -> PathBuf {
+    let documents = UserDirs::new().and_then(|dirs| dirs.document_dir().map(|dir| dir.join("Kindle-pult")));
+    created(documents.unwrap_or_else(|| data_dir().join("books")))
+}
+
+/// Configured output folder, or the default one
+pub fn out_dir(cfg: &HashMap<String, String>) -> Result<PathBuf> {
+    match cfg.get("out_dir").map(String::as_str).unwrap_or_default() {
+        "" => Ok(default_out_dir()),
+        dir => {
+            fs::create_dir_all(dir)?;
+            Ok(PathBuf::from(dir))
+        },
+    }
+}
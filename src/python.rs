@@ -0,0 +1,40 @@
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::config::PultConf;
+use crate::web::{theme_css, Article, HttpMode};
+
+// Python module over the conversion core, built with `maturin build --features python`.
+
+/// Convert the web page at `url` with the saved settings and deliver it like the GUI would,
+/// returning the path of the book
+#[pyfunction]
+fn convert_url(py: Python<'_>, url: String) -> PyResult<String> {
+    // The GIL is released while downloading, other Python threads go on
+    let converted = py.allow_threads(|| {
+        Article::epub_from_url(url.clone(), PultConf::reload(), HttpMode::Live, &|_| {}).map_err(|e| e.to_string())
+    });
+    match converted {
+        Ok(Some((_, path))) => Ok(path.to_string_lossy().into_owned()),
+        Ok(None) => Err(PyValueError::new_err(format!("not a valid URL: {}", url))),
+        Err(e) => Err(PyRuntimeError::new_err(e)),
+    }
+}
+
+/// EPUB of a ReadabiliPy JSON result with the `theme` stylesheet, image sources left as they are
+#[pyfunction]
+#[pyo3(signature = (json, theme = "serif"))]
+fn epub_from_json<'py>(py: Python<'py>, json: &str, theme: &str) -> PyResult<Bound<'py, PyBytes>> {
+    let epub = Article::from_json(json)
+        .and_then(|article| article.build_epub(Vec::new(), theme_css(theme)))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyBytes::new(py, &epub))
+}
+
+#[pymodule]
+fn kindle_pult(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(convert_url, m)?)?;
+    m.add_function(wrap_pyfunction!(epub_from_json, m)?)?;
+    Ok(())
+}
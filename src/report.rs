@@ -0,0 +1,72 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::web::errors::*;
+
+/// What happened to an article during its conversion, saved next to the book on demand
+/// to tell why it looks wrong without rerunning with debug prints
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ConversionReport {
+    pub url: String,
+    pub page_url: String,  // After HTTP and page redirects
+    pub parser: String,  // ReadabiliPy parser that extracted the article
+    pub title: String,
+    pub stripped: BTreeMap<String, usize>,  // Removed element counts, by tag name
+    pub images_found: usize,
+    pub images_downloaded: usize,
+    pub images_duplicate: usize,  // Same URL or same content as another image
+    pub images_decorative: usize,  // Tracking pixels, spacers and icons
+    pub images_failed: usize,
+    pub images_excluded: usize,  // Unticked in the preview
+    pub images_embedded: usize,
+    pub book: PathBuf,
+    pub book_bytes: u64,
+    pub warnings: Vec<String>,
+}
+
+impl ConversionReport {
+    /// Report format from the config, `None` when reports are off
+    pub fn format(cfg_value: &str) -> Option<&'static str> {
+        match cfg_value {
+            "text" => Some("txt"),
+            "json" => Some("json"),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut text = format!(
+            "URL: {}\nPage URL: {}\nParser: {}\nTitle: {}\n",
+            self.url, self.page_url, self.parser, self.title,
+        );
+        if !self.stripped.is_empty() {
+            let stripped = self.stripped.iter().map(|(tag, n)| format!("{} <{}>", n, tag)).collect::<Vec<_>>();
+            text.push_str(&format!("Stripped: {}\n", stripped.join(", ")));
+        }
+        text.push_str(&format!(
+            "Images: {} found, {} downloaded, {} duplicate, {} decorative, {} failed, {} excluded, {} embedded\n",
+            self.images_found, self.images_downloaded, self.images_duplicate, self.images_decorative,
+            self.images_failed, self.images_excluded, self.images_embedded,
+        ));
+        text.push_str(&format!("Book: {} ({} bytes)\n", self.book.display(), self.book_bytes));
+        for warning in &self.warnings {
+            text.push_str(&format!("Warning: {}\n", warning));
+        }
+
+        text
+    }
+
+    /// Save the report next to `book` in the format from the config, returning its path
+    pub fn save(&self, book: &Path, format: &str) -> Result<PathBuf> {
+        let path = book.with_extension(format!("report.{}", format));
+        let contents = match format {
+            "json" => serde_json::to_string_pretty(self)?,
+            _ => self.text(),
+        };
+        fs::write(&path, contents)?;
+
+        Ok(path)
+    }
+}
@@ -1,15 +1,21 @@
-use std::io;
-use std::fs;
-use std::path::{Path, PathBuf};
-use std::cell::Cell;
-use tempfile::Builder;
+// File system and process bound imports, unavailable on wasm32
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io, fs, cell::Cell, collections::HashMap, path::{Path, PathBuf}, sync::{mpsc, Arc}};
+#[cfg(not(target_arch = "wasm32"))]
+use tempfile::{Builder, TempDir};
 
+use std::collections::BTreeMap;
+use std::io::Read;
+
+#[cfg(not(target_arch = "wasm32"))]
 extern crate image;
-use image::io::Reader as ImageReader;
 
 extern crate soup;
 use soup::prelude::*;
 
+extern crate html5ever;
+use html5ever::rcdom::{Handle, NodeData};
+
 extern crate url;
 use url::{Url, ParseError};
 
@@ -18,58 +24,194 @@ use epub_builder::{EpubBuilder, ZipLibrary, EpubContent, ReferenceType, TocEleme
 
 extern crate serde;
 extern crate serde_json;
-use serde::{Deserialize};
+use serde::Deserialize;
+#[cfg(not(target_arch = "wasm32"))]
+use serde::Serialize;
 
-use crate::cmd::{ReadabiliPyCmd, ReadabiliPyParser};
+use crate::config::OUT_FORMATS;
+use crate::i18n::{tr, tr_args};
 
-mod errors {
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cmd::{CalibreCmd, HookCmd, ReadabiliPyCmd, ReadabiliPyParser};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::delivery::{deliver, partial_file, write_atomic};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::images::{content_addressed, fix_extension, thumbnail, ImageOpts};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::http::HttpClient;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::report::ConversionReport;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::paths;
+
+pub mod errors {
     error_chain! {
          foreign_links {
              Io(std::io::Error);
-             HttpRequest(reqwest::Error);
+             HttpRequest(reqwest::Error) #[cfg(not(target_arch = "wasm32"))];
+             JsonParsing(serde_json::Error);
              EpubBuilding(epub_builder::Error);
-             ImageReading(image::ImageError);
+             ImageReading(image::ImageError) #[cfg(not(target_arch = "wasm32"))];
+         }
+
+         errors {
+             Forbidden(url: String) {
+                 description("access forbidden")
+                 display("'{}' answered 403 Forbidden", url)
+             }
+             NotFound(url: String) {
+                 description("not found")
+                 display("'{}' answered 404 Not Found", url)
+             }
+             ServerError(url: String, status: u16) {
+                 description("server error")
+                 display("'{}' answered {} server error", url, status)
+             }
+             HttpStatus(url: String, status: u16) {
+                 description("unexpected HTTP status")
+                 display("'{}' answered HTTP {}", url, status)
+             }
          }
     }
 }
 
 use errors::*;
 
+/// What went wrong in plain words, for error dialogs
+pub fn explain_error(e: &Error) -> &'static str {
+    match e.kind() {
+        #[cfg(not(target_arch = "wasm32"))]
+        ErrorKind::HttpRequest(_) => tr("error-download"),
+        ErrorKind::Forbidden(_) => tr("error-forbidden"),
+        ErrorKind::NotFound(_) => tr("error-not-found"),
+        ErrorKind::ServerError(..) => tr("error-server"),
+        ErrorKind::HttpStatus(..) => tr("error-http"),
+        ErrorKind::Io(_) => tr("error-io"),
+        ErrorKind::JsonParsing(_) => tr("error-extract"),
+        ErrorKind::EpubBuilding(_) => tr("error-build"),
+        #[cfg(not(target_arch = "wasm32"))]
+        ErrorKind::ImageReading(_) => tr("error-image"),
+        _ => tr("error-crashed"),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Copy, Clone)]
 enum DLFileType {
     Text,
     Image,
 }
 
+/// Where HTTP responses come from
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub enum HttpMode {
+    Live,
+    Record(PathBuf),  // Fetch from network and save every response as a fixture in this dir
+    Replay(PathBuf),  // Never touch the network, serve responses from the fixtures in this dir
+}
+
+/// A recorded response, indexed by requested URL in `index.json`
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Serialize, Deserialize, Debug)]
+struct Fixture {
+    final_url: String,  // URL after redirects
+    filename: String,  // Name the response was saved with
+    file: String,  // Fixture file inside the record dir
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_fixtures(dir: &Path) -> HashMap<String, Fixture> {
+    fs::read_to_string(dir.join("index.json")).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 struct Downloader {
     path: PathBuf,  // Path where all file are collected;
     file_type: Cell<DLFileType>,  // Mutate type with `.set` and `.get` Cell methods
+    mode: HttpMode,
+    client: HttpClient,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Downloader {
-    fn new(path: PathBuf, file_type: DLFileType) -> Self {
+    fn new(path: PathBuf, file_type: DLFileType, mode: HttpMode, client: HttpClient) -> Self {
         Self {
             path,
             file_type: Cell::new(file_type),
+            mode,
+            client,
         }
     }  // new_for_path
 
-    fn download_from(&self, target: Url) -> Result<String> {
-        // Make HTTP request for target file
-        let mut response = reqwest::blocking::get(target.as_str())?; // TODO: use non-blocking async
+    /// Download `target` in the downloader dir, returns the local path and the URL the
+    /// content was finally served from, after redirects
+    fn download_from(&self, target: Url) -> Result<(String, Url)> {
+        if let HttpMode::Replay(dir) = &self.mode {
+            return self.replay(dir, &target)
+        }
+
+        // Local pages (dropped files, converted mails) and their images are just copied
+        if target.scheme() == "file" {
+            let source = target.to_file_path().map_err(|_| format!("Invalid file URL: {}", target))?;
+            if !fs::metadata(&source)?.is_file() {
+                bail!("{} is not a regular file", source.display());  // e.g. /dev/zero would never end
+            }
+            let local_abs_path = self.path.join(source.file_name().unwrap_or_else(|| "tmp.bin".as_ref()));
+            fs::copy(&source, &local_abs_path)?;
+            return Ok((local_abs_path.into_os_string().into_string().unwrap(), target))
+        }
+
+        // Make HTTP request for target file, waiting when the site asks to slow down
+        let mut response = self.client.get(&target)?; // TODO: use non-blocking async
+        for attempt in 0..MAX_RATE_LIMIT_RETRIES {
+            let status = response.status();
+            if status != reqwest::StatusCode::TOO_MANY_REQUESTS && status != reqwest::StatusCode::SERVICE_UNAVAILABLE {
+                break
+            }
+            // Retry-After in seconds, HTTP dates and missing headers fall back to exponential backoff
+            let wait = response.headers().get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .unwrap_or(1 << attempt)
+                .min(MAX_RETRY_WAIT_SECS);
+            println!("'{}' answered {}, retrying in {}s", target, status, wait);
+            std::thread::sleep(std::time::Duration::from_secs(wait));
+            response = self.client.get(&target)?;
+        }
+
+        // Error pages would make sad little books
+        let status = response.status();
+        if !status.is_success() {
+            let url = target.to_string();
+            match status.as_u16() {
+                403 => bail!(ErrorKind::Forbidden(url)),
+                404 | 410 => bail!(ErrorKind::NotFound(url)),
+                code if status.is_server_error() => bail!(ErrorKind::ServerError(url, code)),
+                code => bail!(ErrorKind::HttpStatus(url, code)),
+            }
+        }
+        let final_url = response.url().clone();
 
-        // Choosing filename
+        // Choosing filename, from the path only: query strings (`?w=800`) and characters
+        // unsafe in filenames are left out, the extension of images is fixed once downloaded
         let filename = response
             .url()
             .path_segments()
             .and_then(|segments| segments.last())
-            .and_then(|name| if name.is_empty() { None } else { Some(name) })
-            .unwrap_or("tmp.bin");
+            .and_then(safe_filename)
+            .unwrap_or_else(|| "tmp.bin".to_string());
 
         println!("file to download: '{:?}'", filename);
 
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+
         // Locate destination
-        let local_abs_path = self.path.join(filename);
+        let mut local_abs_path = self.path.join(filename);
         println!("will be located under: '{:?}'", local_abs_path);
         let mut destination = fs::File::create(local_abs_path.clone())?;
 
@@ -77,16 +219,51 @@ impl Downloader {
         match self.file_type.get() {
             DLFileType::Text => {
                 let html_string = response.text()?;
-                io::copy(&mut html_string.as_bytes(), &mut destination)
-                    .expect("Failed to copy HTML file to destination");
+                self.client.pace(html_string.len());  // Already downloaded, later ones wait for it
+                io::copy(&mut html_string.as_bytes(), &mut destination)?;
             },  // if HTML
             DLFileType::Image => {
-                io::copy(&mut response, &mut destination)
-                    .expect("Failed to copy image to destination");
+                io::copy(&mut self.client.throttle(&mut response), &mut destination)?;
+                drop(destination);
+                local_abs_path = fix_extension(&local_abs_path, content_type.as_deref())?;
             }  // else if Image
         }  // match file type
 
-        Ok(local_abs_path.into_os_string().into_string().unwrap())
+        if let HttpMode::Record(dir) = &self.mode {
+            self.record(dir, &target, &final_url, &local_abs_path)?;
+        }
+
+        Ok((local_abs_path.into_os_string().into_string().unwrap(), final_url))
+    }
+
+    fn record(&self, dir: &Path, target: &Url, final_url: &Url, local_abs_path: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let mut fixtures = load_fixtures(dir);
+
+        let filename = local_abs_path.file_name().unwrap().to_string_lossy().to_string();
+        let file = format!("{:04}-{}", fixtures.len(), filename);
+        fs::copy(local_abs_path, dir.join(&file))?;
+        println!("recorded '{}' as '{}'", target, file);
+
+        fixtures.insert(target.to_string(), Fixture { final_url: final_url.to_string(), filename, file });
+        fs::write(dir.join("index.json"), serde_json::to_string_pretty(&fixtures)?)?;
+
+        Ok(())
+    }
+
+    fn replay(&self, dir: &Path, target: &Url) -> Result<(String, Url)> {
+        let fixtures = load_fixtures(dir);
+        let fixture = match fixtures.get(target.as_str()) {
+            Some(fixture) => fixture,
+            None => bail!("no recorded response for '{}' in '{}'", target, dir.display()),
+        };
+        println!("replaying '{}' (was '{}') from '{}'", target, fixture.final_url, fixture.file);
+
+        let local_abs_path = self.path.join(&fixture.filename);
+        fs::copy(dir.join(&fixture.file), &local_abs_path)?;
+        let final_url = Url::parse(&fixture.final_url).unwrap_or_else(|_| target.clone());
+
+        Ok((local_abs_path.into_os_string().into_string().unwrap(), final_url))
     }
 }
 
@@ -97,143 +274,1551 @@ pub struct Article {
     date: Option<String>,
     content: Option<String>,
     plain_content: Option<String>,  // plain content of the article, preserving the HTML structure
+    #[serde(default)]
+    tags: Vec<String>,  // Not from ReadabiliPy, set by the user
+    #[serde(default)]
+    source: Option<String>,  // Page URL, not from ReadabiliPy
+    #[serde(default)]
+    description: Option<String>,  // Set by the user
+    #[serde(default)]
+    lang: Option<String>,  // Set by the user
+}
+
+/// URL relative links of the page `html` served from `url` resolve against: its `<base href>`
+/// when set, `url` otherwise
+pub fn page_base(html: &str, url: &Url) -> Url {
+    Soup::new(html).tag("base").find()
+        .and_then(|base| base.get("href"))
+        .and_then(|href| url.join(href.trim()).ok())
+        .unwrap_or_else(|| url.clone())
+}
+
+/// Retries of a rate limited request, and longest wait between them
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_RATE_LIMIT_RETRIES: u32 = 4;
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_RETRY_WAIT_SECS: u64 = 60;
+
+/// Longest image embedded as data URI kept in the content
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_DATA_URI_BYTES: usize = 256 * 1024;
+
+/// Side of the image thumbnails shown before building, in pixels
+#[cfg(not(target_arch = "wasm32"))]
+const THUMBNAIL_SIZE: u32 = 96;
+
+/// Most redirects done by pages themselves followed for a single conversion
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_SOFT_REDIRECTS: usize = 5;
+
+/// Target of a redirect done by the page `html` served from `url` rather than by HTTP: a
+/// `<meta http-equiv="refresh">` or a `location.href = "..."` script. Only pages with
+/// hardly any text count, so articles with a refresh timer or analytics are left alone.
+pub fn soft_redirect(html: &str, url: &Url) -> Option<Url> {
+    let soup = Soup::new(html);
+    let text_len = soup.tag("body").find()
+        .map(|body| {
+            let scripts_len: usize = body.tag("script").find_all().map(|script| script.text().len()).sum();
+            body.text().len().saturating_sub(scripts_len)
+        })
+        .unwrap_or(0);
+    if text_len > 500 {
+        return None
+    }
+
+    // <meta http-equiv="refresh" content="0; url=https://example.com/">
+    let refresh = soup.tag("meta").find_all()
+        .find(|meta| matches!(meta.get("http-equiv"), Some(equiv) if equiv.eq_ignore_ascii_case("refresh")))
+        .and_then(|meta| meta.get("content"))
+        .and_then(|content| {
+            let (delay, target) = content.split_at(content.find([';', ','])?);
+            let delay: f64 = delay.trim().parse().ok()?;
+            let target = target[1..].trim();
+            let target = match target.to_lowercase().find("url") {
+                Some(0) => target[3..].trim_start().strip_prefix('=')?.trim(),
+                _ => target,
+            };
+            if delay > 10.0 { None } else { Some(target.trim_matches(|c| c == '\'' || c == '"').to_string()) }
+        });
+
+    // location.href = "...", window.location = '...', location.replace("...")
+    let script = || soup.tag("script").find_all().find_map(|script| {
+        let code = script.text();
+        let start = ["location.href", "location.replace", "location.assign", "location"].iter()
+            .find_map(|call| code.find(call).map(|i| i + call.len()))?;
+        let rest = code[start..].trim_start().trim_start_matches(['=', '(']).trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let end = rest[1..].find(quote)?;
+        Some(rest[1..end + 1].to_string())
+    });
+
+    refresh.or_else(script)
+        .filter(|target| !target.is_empty())
+        .and_then(|target| url.join(&target).ok())
+        .filter(|target| target != url && (target.scheme() == "http" || target.scheme() == "https"))
+}
+
+/// Whether the image at `url` can be downloaded for the page served from `page_url`: web
+/// images always, local files only for local pages, so web pages can't pull files off the disk
+pub fn fetchable_image(url: &Url, page_url: &Url) -> bool {
+    match url.scheme() {
+        "http" | "https" => true,
+        "file" => page_url.scheme() == "file",
+        _ => false,
+    }
+}
+
+/// Whether `bytes` look like a complete EPUB: a ZIP starting with the uncompressed
+/// `mimetype` entry and ending with its central directory
+pub fn is_epub(bytes: &[u8]) -> bool {
+    const END_OF_CENTRAL_DIR: &[u8] = b"PK\x05\x06";
+    // The central directory end record is in the last 22 bytes, plus the archive comment
+    let tail = &bytes[bytes.len().saturating_sub(22 + 0xFFFF)..];
+    bytes.starts_with(b"PK\x03\x04")
+        && bytes.get(30..58) == Some(&b"mimetypeapplication/epub+zip"[..])
+        && tail.windows(4).any(|w| w == END_OF_CENTRAL_DIR)
+}
+
+/// Lowercase, dash separated version of `text`, safe for filenames
+pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// `name` with the characters unsafe in filenames (path separators, spaces, quotes) replaced
+/// by `_`, `None` when nothing meaningful is left
+pub fn safe_filename(name: &str) -> Option<String> {
+    let safe = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect::<String>();
+    Some(safe).filter(|safe| !safe.trim_matches(|c| c == '.' || c == '_').is_empty())
+}
+
+/// Book filename from `name` typed by the user: made safe and given the `.epub` extension in
+/// place of any book format one, other formats are converted from the EPUB later
+pub fn epub_filename(name: &str) -> Option<String> {
+    let safe = safe_filename(name.trim())?;
+    let safe = safe.trim_start_matches('.');  // No hidden files
+    let stem = match safe.rsplit_once('.') {
+        Some((stem, ext)) if OUT_FORMATS.contains(&ext.to_lowercase().as_str()) => stem,
+        _ => safe,
+    };
+    Some(format!("{}.epub", safe_filename(stem)?))
+}
+
+/// CSS themes for the generated books
+pub const THEMES: &[&str] = &["serif", "sans", "none"];
+
+/// Stylesheet of the `theme` named one
+pub fn theme_css(theme: &str) -> &'static str {
+    match theme {
+        "serif" => "body { font-family: serif; line-height: 1.4; }\n\
+                    h1, h2, h3 { font-family: serif; }\n\
+                    img { max-width: 100%; }\n",
+        "sans" => "body { font-family: sans-serif; line-height: 1.4; }\n\
+                   h1, h2, h3 { font-family: sans-serif; }\n\
+                   img { max-width: 100%; }\n",
+        _ => "",
+    }
+}
+
+/// Escape `text` to be used inside XHTML
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Byte ranges of the `attr` attribute in `tag`: the whole attribute, with its leading
+/// space, and its value without quotes
+fn attr_span(tag: &str, attr: &str) -> Option<(usize, usize, usize, usize)> {
+    let lower = tag.to_lowercase();
+    let attr_start = [' ', '\n', '\t'].iter()
+        .find_map(|sep| lower.find(&format!("{}{}=", sep, attr)))?;
+    let start = attr_start + attr.len() + 2;
+
+    match tag[start..].chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => {
+            let end = tag[start + 1..].find(quote).map(|i| start + 1 + i).unwrap_or_else(|| tag.len());
+            Some((attr_start, start + 1, end, (end + 1).min(tag.len())))
+        },
+        _ => {
+            let end = tag[start..].find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .map(|i| start + i).unwrap_or_else(|| tag.len());
+            Some((attr_start, start, end, end))
+        },
+    }
+}
+
+/// Value of the `attr` attribute of `tag`, with the entities `escape_xml` writes decoded
+pub fn get_attr(tag: &str, attr: &str) -> Option<String> {
+    attr_span(tag, attr).map(|(_, value_start, value_end, _)| {
+        tag[value_start..value_end]
+            .replace("&quot;", "\"")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&")
+    })
+}
+
+/// `tag` with the value of its `attr` attribute replaced by `value`, or added if missing
+pub fn set_attr(tag: &str, attr: &str, value: &str) -> String {
+    let value = escape_xml(value);
+    match attr_span(tag, attr) {
+        Some((_, value_start, value_end, _)) => format!("{}{}{}", &tag[..value_start], value, &tag[value_end..]),
+        None => {
+            let end = tag.trim_end_matches('>').trim_end_matches('/').trim_end().len();
+            format!("{} {}=\"{}\"{}", &tag[..end], attr, value, &tag[end..])
+        },
+    }
+}
+
+/// `tag` without its `attr` attribute
+pub fn remove_attr(tag: &str, attr: &str) -> String {
+    match attr_span(tag, attr) {
+        Some((attr_start, _, _, attr_end)) => format!("{}{}", &tag[..attr_start], &tag[attr_end..]),
+        None => tag.to_string(),
+    }
+}
+
+/// Readable version of an image filename, e.g. "Golden gate bridge" for
+/// `golden_gate-bridge.jpg?w=800`. Empty for names that mean nothing (hashes, numbers).
+pub fn alt_from_filename(src: &str) -> String {
+    let name = src.split(['?', '#']).next().unwrap_or_default();
+    let name = name.rsplit('/').next().unwrap_or_default();
+    let stem = match name.rfind('.') {
+        Some(dot) if dot > 0 => &name[..dot],
+        _ => name,
+    };
+
+    let words = stem.split(|c: char| c == '-' || c == '_' || c == '+' || c == '.' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        // Hashes, ids and sizes like `800x600` carry no meaning
+        .filter(|word| word.chars().any(|c| c.is_alphabetic()) && !word.chars().any(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>();
+    let text = words.join(" ").to_lowercase();
+
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Candidate of a `srcset` closest to `width` pixels: the smallest one at least as wide,
+/// the widest one otherwise. Density (`2x`) candidates are ranked as multiples of `width`.
+pub fn srcset_candidate(srcset: &str, width: u32) -> Option<String> {
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break
+        }
+        let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = &rest[..url_end];
+        rest = &rest[url_end..];
+
+        // A URL ending with a comma has no descriptor
+        let (url, descriptor) = if url.ends_with(',') {
+            (url.trim_end_matches(','), "")
+        } else {
+            let descriptor_end = rest.find(',').unwrap_or(rest.len());
+            let descriptor = rest[..descriptor_end].trim();
+            rest = &rest[descriptor_end..];
+            (url, descriptor)
+        };
+
+        let size = if let Some(w) = descriptor.strip_suffix('w') {
+            w.parse::<f64>().ok()
+        } else if let Some(x) = descriptor.strip_suffix('x') {
+            x.parse::<f64>().ok().map(|x| x * width as f64)
+        } else {
+            Some(width as f64)  // No descriptor means 1x
+        };
+        // Malformed descriptors (`NaNw`, `infx`) can't be ranked
+        if let Some(size) = size.filter(|size| size.is_finite()) {
+            candidates.push((url.to_string(), size));
+        }
+    }
+
+    let target = width as f64;
+    let fitting = candidates.iter()
+        .filter(|(_, size)| *size >= target)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    let widest = candidates.iter().max_by(|a, b| a.1.total_cmp(&b.1));
+
+    fitting.or(widest).map(|(url, _)| url.clone())
+}
+
+/// Elements without content, self-closed in XHTML
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements removed or unwrapped by `to_xhtml`
+const STRIPPED_ELEMENTS: &[&str] = &[
+    "script", "template", "embed", "param", "input", "select", "frameset", "noscript", "iframe", "frame", "form",
+    "object", "button", "textarea",
+];
+
+/// Whether `name` can be used as an XML attribute name
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.' || c == ':')
 }
 
+/// Serialize `node` and its children as well-formed XHTML, without scripts, frames, forms
+/// and event handlers
+fn write_xhtml(node: &Handle, out: &mut String) {
+    let write_children = |out: &mut String| {
+        for child in node.children.borrow().iter() {
+            write_xhtml(child, out);
+        }
+    };
+
+    match &node.data {
+        NodeData::Text { contents } => out.push_str(&escape_xml(&contents.borrow())),
+        NodeData::Element { name, attrs, .. } => {
+            let tag = name.local.to_string();
+
+            // Executable and interactive elements are dropped, keeping their readable fallback
+            match tag.as_str() {
+                "script" | "template" | "embed" | "param" | "input" | "select" | "frameset" => return,
+                "noscript" => {
+                    // Parsed as text, it often holds the plain <img> of lazy loaded images
+                    let fallback = node.children.borrow().iter()
+                        .filter_map(|child| match &child.data {
+                            NodeData::Text { contents } => Some(contents.borrow().to_string()),
+                            _ => None,
+                        })
+                        .collect::<String>();
+                    out.push_str(&to_xhtml(&fallback));
+                    return
+                },
+                "iframe" | "frame" => {
+                    let src = attrs.borrow().iter()
+                        .find(|attr| &*attr.name.local == "src")
+                        .map(|attr| attr.value.to_string())
+                        .filter(|src| !src.trim().to_lowercase().starts_with("javascript:"));
+                    if let Some(src) = src {
+                        out.push_str(&format!("<p><a href=\"{0}\">{0}</a></p>", escape_xml(&src)));
+                    }
+                    return
+                },
+                "form" | "object" | "button" | "textarea" => {
+                    write_children(out);
+                    return
+                },
+                _ => {},
+            }
+
+            out.push('<');
+            out.push_str(&tag);
+            for attr in attrs.borrow().iter() {
+                let attr_name = match &attr.name.prefix {
+                    Some(prefix) => format!("{}:{}", prefix, attr.name.local),
+                    None => attr.name.local.to_string(),
+                };
+                let executable = attr_name.to_lowercase().starts_with("on")
+                    || attr.value.trim().to_lowercase().starts_with("javascript:");
+                if is_xml_name(&attr_name) && !executable {
+                    out.push_str(&format!(" {}=\"{}\"", attr_name, escape_xml(&attr.value)));
+                }
+            }
+            // Inline SVG and MathML need their namespace in XHTML
+            match tag.as_str() {
+                "svg" => out.push_str(" xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\""),
+                "math" => out.push_str(" xmlns=\"http://www.w3.org/1998/Math/MathML\""),
+                _ => {},
+            }
+
+            if VOID_ELEMENTS.contains(&tag.as_str()) {
+                out.push_str(" />");
+                return
+            }
+            out.push('>');
+            write_children(out);
+            out.push_str(&format!("</{}>", tag));
+        },
+        NodeData::Document => write_children(out),
+        _ => {},  // Comments, doctypes and processing instructions are dropped
+    }
+}
+
+/// Parse `html` like a browser would and serialize it back as well-formed XHTML: tags are
+/// closed, void elements self-closed, entities and stray `&` escaped, scripts and other
+/// executable or interactive content removed
+pub fn to_xhtml(html: &str) -> String {
+    let soup = Soup::new(html);
+    let mut xhtml = String::with_capacity(html.len());
+    if let Some(body) = soup.tag("body").find() {
+        for child in body.children.borrow().iter() {
+            write_xhtml(child, &mut xhtml);
+        }
+    }
+
+    xhtml
+}
+
+/// Wrap `body` in a complete XHTML page linked to the book stylesheet
+pub fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="stylesheet.css" />
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        title = escape_xml(title),
+        body = body,
+    )
+}
+
+/// Piece of the article shown by the built-in preview
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviewBlock {
+    Heading(String),
+    Paragraph(String),
+    Quote(String),
+    Image(Url),  // Absolute source
+}
+
+/// Elements starting a new block in the preview, the others are part of the text around them
+const PREVIEW_BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "dd", "div", "dl", "dt", "figcaption", "figure", "footer",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section",
+    "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+/// Append the preview blocks of `node` and its children to `blocks`, with image sources
+/// resolved against `base`
+fn preview_blocks(node: &Handle, base: &Url, blocks: &mut Vec<PreviewBlock>) {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let tag = match &node.data {
+        NodeData::Element { name, .. } => name.local.to_string(),
+        NodeData::Text { contents } => {
+            let text = normalize(&contents.borrow());
+            if !text.is_empty() {
+                blocks.push(PreviewBlock::Paragraph(text));
+            }
+            return
+        },
+        _ => return,
+    };
+    if STRIPPED_ELEMENTS.contains(&tag.as_str()) || tag == "style" {
+        return
+    }
+
+    // Quotes are shown as a single block, one line per block inside
+    if tag == "blockquote" {
+        let mut inner = Vec::new();
+        for child in node.children.borrow().iter() {
+            preview_blocks(child, base, &mut inner);
+        }
+        let (mut lines, mut images) = (Vec::new(), Vec::new());
+        for block in inner {
+            match block {
+                PreviewBlock::Heading(line) | PreviewBlock::Paragraph(line) | PreviewBlock::Quote(line) => lines.push(line),
+                image => images.push(image),
+            }
+        }
+        if !lines.is_empty() {
+            blocks.push(PreviewBlock::Quote(lines.join("\n")));
+        }
+        blocks.extend(images);
+        return
+    }
+
+    // Containers of blocks are walked through
+    let has_blocks = node.children.borrow().iter().any(|child| matches!(
+        &child.data,
+        NodeData::Element { name, .. } if PREVIEW_BLOCK_ELEMENTS.contains(&&*name.local)
+    ));
+    if has_blocks {
+        for child in node.children.borrow().iter() {
+            preview_blocks(child, base, blocks);
+        }
+        return
+    }
+
+    let text = normalize(&node.text());
+    if !text.is_empty() {
+        blocks.push(match tag.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => PreviewBlock::Heading(text),
+            _ => PreviewBlock::Paragraph(text),
+        });
+    }
+    let images = match tag.as_str() {
+        "img" => vec![node.clone()],
+        _ => node.tag("img").find_all().collect(),
+    };
+    for img in images {
+        if let Some(url) = img.get("src").and_then(|src| base.join(&src).ok()) {
+            blocks.push(PreviewBlock::Image(url));
+        }
+    }
+}
+
+/// Pipeline milestones, reported while converting
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Fetch,
+    Extract,
+    Images(usize, usize),  // i of n images downloaded
+    Build,
+    Deliver,
+    Done,
+    Failed(String),
+}
+
+impl Progress {
+    /// Completed share of the whole conversion, from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        match self {
+            Progress::Fetch => 0.0,
+            Progress::Extract => 0.1,
+            Progress::Images(i, n) => 0.2 + 0.6 * (*i as f64 / (*n).max(1) as f64),
+            Progress::Build => 0.8,
+            Progress::Deliver => 0.9,
+            Progress::Done | Progress::Failed(_) => 1.0,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Progress::Fetch => tr("fetching-page").into(),
+            Progress::Extract => tr("extracting-article").into(),
+            Progress::Images(i, n) => tr_args("downloading-images", &[("done", *i), ("total", *n)]),
+            Progress::Build => tr("building-epub").into(),
+            Progress::Deliver => tr("delivering-book").into(),
+            Progress::Done => tr("done").into(),
+            Progress::Failed(e) => format!("{}: {}", tr("failed"), e),
+        }
+    }
+}
+
+/// Image ready to be embedded in the EPUB
+pub struct EpubImage {
+    pub filename: String,
+    pub data: Box<dyn Read + Send>,  // Encoded image, possibly streamed from disk
+    pub mime: String,
+}
+
+// Pure part of the pipeline: no file system or process access, so it also builds for wasm32
 impl Article {
-    pub fn epub_from_url(target: String) -> Result<()> {
-        // Parse target URL
-        let target_url = Url::parse(&target);
+    /// Deserialize an article from ReadabiliPy JSON output
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or_default()
+    }
+
+    pub fn byline(&self) -> &str {
+        self.byline.as_deref().unwrap_or_default()
+    }
+
+    pub fn date(&self) -> &str {
+        self.date.as_deref().unwrap_or_default()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn set_title(&mut self, title: String) {
+        self.title = Some(title);
+    }
+
+    pub fn set_byline(&mut self, byline: String) {
+        self.byline = Some(byline);
+    }
+
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
+    pub fn set_source(&mut self, source: String) {
+        self.source = Some(source);
+    }
+
+    pub fn set_date(&mut self, date: String) {
+        self.date = Some(date);
+    }
+
+    pub fn set_description(&mut self, description: String) {
+        self.description = Some(description);
+    }
+
+    pub fn set_lang(&mut self, lang: String) {
+        self.lang = Some(lang);
+    }
+
+    /// Plain text of the first `n` paragraphs, for previews
+    pub fn summary(&self, n: usize) -> String {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return String::new(),
+        };
+
+        let soup = Soup::new(content);
+        soup.tag("p").find_all()
+            .map(|p| p.text().trim().to_string())
+            .filter(|p| !p.is_empty())
+            .take(n)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// The content as headings, paragraphs, quotes and images in reading order, for a preview
+    /// without a browser engine
+    pub fn preview_blocks(&self, base: &Url) -> Vec<PreviewBlock> {
+        let mut blocks = Vec::new();
+        if let Some(body) = self.content.as_deref().and_then(|content| Soup::new(content).tag("body").find()) {
+            for child in body.children.borrow().iter() {
+                preview_blocks(child, base, &mut blocks);
+            }
+        }
+        blocks
+    }
+
+    /// Absolute URLs of the images found in the article content
+    pub fn image_urls(&self, base: &Url) -> Vec<Url> {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return Vec::new(),  // Empty vector
+        };
+
+        let mut urls = Vec::new();
+        let soup = Soup::new(content);
+
+        for img in soup.tag("img").find_all() {
+            let image_url = match img.get("src") {
+                Some(src) => src,
+                None => continue,  // Nothing to download
+            };
+
+            // Make sure URL is absolute and add it to urls vector;
+            match Url::parse(&image_url) {
+                Ok(url) => {
+                    urls.push(url);
+                },  // Already absolute, send to vector
+                Err(ParseError::RelativeUrlWithoutBase) => {
+                    println!("Relative URL: {}", &image_url);
+                    match base.join(&image_url) {
+                        Ok(absolute_url) => {
+                            println!("absolute URL: {}", &absolute_url);
+                            urls.push(absolute_url);
+                        },
+                        Err(e) => println!("errore: {}", e),
+                    }
+                },  // Relative URL error
+                Err(e) => {
+                    println!("errore: {}", e);
+                }  // Unknown error, skip image
+            }  // match url parse
+        };
+
+        println!("Image URLS: {:?}", urls);
+        urls
+    }
+
+    /// Drop the `<img>` tags whose source, resolved against `base`, is in `excluded`
+    pub fn remove_images(&mut self, base: &Url, excluded: &[Url]) {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return,
+        };
+
+        let mut kept = String::with_capacity(content.len());
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find("<img") {
+            let end = rest[start..].find('>').map(|i| start + i + 1).unwrap_or_else(|| rest.len());
+            let tag = &rest[start..end];
+            let src = Soup::new(tag).tag("img").find().and_then(|img| img.get("src"));
+            let drop = matches!(src.and_then(|src| base.join(&src).ok()), Some(url) if excluded.contains(&url));
+
+            kept.push_str(&rest[..start]);
+            if !drop {
+                kept.push_str(tag);
+            }
+            rest = &rest[end..];
+        }
+        kept.push_str(rest);
+
+        self.content = Some(kept);
+    }
+
+    /// Normalize the content to XHTML and strip its scripts, frames, forms and event handlers,
+    /// returns how many elements of each kind were stripped
+    pub fn sanitize(&mut self) -> BTreeMap<String, usize> {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return BTreeMap::new(),
+        };
+
+        let soup = Soup::new(content);
+        let stripped = STRIPPED_ELEMENTS.iter()
+            .map(|tag| (tag.to_string(), soup.tag(*tag).find_all().count()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        self.content = Some(to_xhtml(content));
+
+        stripped
+    }
+
+    /// Drop images embedded as data URIs longer than `max_bytes`, returns how many
+    pub fn remove_data_images(&mut self, max_bytes: usize) -> usize {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return 0,
+        };
+
+        let mut removed = 0;
+        let mut kept = String::with_capacity(content.len());
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find("<img") {
+            let end = rest[start..].find('>').map(|i| start + i + 1).unwrap_or(rest.len());
+            let tag = &rest[start..end];
+            let oversized = matches!(
+                Soup::new(tag).tag("img").find().and_then(|img| img.get("src")),
+                Some(src) if src.starts_with("data:") && src.len() > max_bytes
+            );
+
+            kept.push_str(&rest[..start]);
+            if oversized {
+                removed += 1;
+            } else {
+                kept.push_str(tag);
+            }
+            rest = &rest[end..];
+        }
+        kept.push_str(rest);
+
+        self.content = Some(kept);
+        removed
+    }
+
+    /// Cut the content at a tag boundary to keep it under `max_bytes`, closing the tags left
+    /// open. Returns whether it was cut.
+    pub fn truncate(&mut self, max_bytes: usize) -> bool {
+        let content = match &self.content {
+            Some(content) if content.len() > max_bytes => content,
+            _ => return false,
+        };
+
+        let mut cut = max_bytes;
+        while !content.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let cut = content[..cut].rfind('<').unwrap_or(cut);
+        self.content = Some(to_xhtml(&content[..cut]));
+
+        true
+    }
+
+    /// Add a visible note for the reader at the end of the content
+    pub fn add_notice(&mut self, notice: &str) {
+        let content = self.content.get_or_insert_with(String::new);
+        content.push_str(&format!("\n<p class=\"notice\"><em>{}</em></p>\n", escape_xml(notice)));
+    }
+
+    /// Use the `srcset` candidate closest to `width` pixels as `src` of images declaring one.
+    /// With no width (no device set) the widest candidate is used.
+    pub fn pick_srcset(&mut self, width: u32) {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return,
+        };
+        let width = if width == 0 { u32::MAX } else { width };
+
+        let mut picked = String::with_capacity(content.len());
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find("<img") {
+            let end = rest[start..].find('>').map(|i| start + i + 1).unwrap_or_else(|| rest.len());
+            let tag = &rest[start..end];
+            let srcset = Soup::new(tag).tag("img").find().and_then(|img| img.get("srcset"));
+
+            picked.push_str(&rest[..start]);
+            match srcset.and_then(|srcset| srcset_candidate(&srcset, width)) {
+                Some(src) => picked.push_str(&remove_attr(&set_attr(tag, "src", &src), "srcset")),
+                None => picked.push_str(tag),
+            }
+            rest = &rest[end..];
+        }
+        picked.push_str(rest);
+
+        self.content = Some(picked);
+    }
+
+    /// Give images without an `alt` text one from their figure caption, their title or their
+    /// filename, in this order, so screen readers don't announce bare filenames
+    pub fn fill_alt_texts(&mut self) {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return,
+        };
+
+        let mut filled = String::with_capacity(content.len());
+        let mut pos = 0;
+        while let Some(offset) = content[pos..].find("<img") {
+            let start = pos + offset;
+            let end = content[start..].find('>').map(|i| start + i + 1).unwrap_or(content.len());
+            let tag = &content[start..end];
+            filled.push_str(&content[pos..start]);
+            pos = end;
+
+            let img = match Soup::new(tag).tag("img").find() {
+                Some(img) if img.get("alt").is_none() => img,
+                _ => {
+                    filled.push_str(tag);
+                    continue
+                },
+            };
+
+            // Caption of the figure around the image, if any
+            let figure_start = content[..start].rfind("<figure");
+            let figure_end = content[start..].find("</figure>").map(|i| start + i);
+            let caption = match (figure_start, figure_end) {
+                (Some(figure_start), Some(figure_end)) if !content[figure_start..start].contains("</figure>") => {
+                    Soup::new(&content[figure_start..figure_end]).tag("figcaption").find()
+                        .map(|caption| caption.text().split_whitespace().collect::<Vec<_>>().join(" "))
+                },
+                _ => None,
+            };
+
+            let alt = caption.filter(|caption| !caption.is_empty())
+                .or_else(|| img.get("title").filter(|title| !title.trim().is_empty()))
+                .or_else(|| img.get("src").map(|src| alt_from_filename(&src)))
+                .unwrap_or_default();
+            filled.push_str(&set_attr(tag, "alt", alt.trim()));
+        }
+        filled.push_str(&content[pos..]);
+
+        self.content = Some(filled);
+    }
+
+    /// Point images to their file in the book, `names` maps image URLs to resource filenames
+    pub fn relink_images(&mut self, base: &Url, names: &[(Url, String)]) {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return,
+        };
+
+        let mut relinked = String::with_capacity(content.len());
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find("<img") {
+            let end = rest[start..].find('>').map(|i| start + i + 1).unwrap_or_else(|| rest.len());
+            let tag = &rest[start..end];
+            let src = Soup::new(tag).tag("img").find().and_then(|img| img.get("src"));
+            let name = src.and_then(|src| base.join(&src).ok())
+                .and_then(|url| names.iter().find(|(u, _)| *u == url))
+                .map(|(_, name)| name);
+
+            relinked.push_str(&rest[..start]);
+            match name {
+                Some(name) => relinked.push_str(&set_attr(tag, "src", name)),
+                None => relinked.push_str(tag),
+            }
+            rest = &rest[end..];
+        }
+        relinked.push_str(rest);
+
+        self.content = Some(relinked);
+    }
+
+    /// Keep in-article navigation working in the book: links to this very page become
+    /// fragment links, `<a name>` anchors become ids, duplicate ids are renamed and links to
+    /// anchors missing from the article (e.g. "back to top") are turned into plain text.
+    /// Links are resolved against `base` and compared with `page_url`, where the page was served.
+    pub fn fix_internal_links(&mut self, base: &Url, page_url: &Url) {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return,
+        };
+
+        // Tags are at `<` followed by a letter, their attributes can be read without parsing
+        let tags = || content.match_indices('<')
+            .filter(|(start, _)| content[start + 1..].starts_with(|c: char| c.is_ascii_alphabetic()))
+            .map(|(start, _)| (start, content[start..].find('>').map(|i| start + i + 1).unwrap_or(content.len())));
+
+        let mut ids = Vec::new();
+        for (start, end) in tags() {
+            let tag = &content[start..end];
+            if let Some(id) = get_attr(tag, "id").or_else(|| get_attr(tag, "name").filter(|_| tag.starts_with("<a "))) {
+                ids.push(id);
+            }
+        }
+
+        let mut fixed = String::with_capacity(content.len());
+        let mut seen: Vec<String> = Vec::new();
+        let mut pos = 0;
+        for (start, end) in tags() {
+            let mut tag = content[start..end].to_string();
+
+            // Anchors: ids are made unique, named anchors get their name as id
+            let anchor = tag.starts_with("<a ");
+            let id = get_attr(&tag, "id").or_else(|| get_attr(&tag, "name").filter(|_| anchor));
+            if let Some(id) = id {
+                let mut unique = id.clone();
+                let mut n = 1;
+                while seen.contains(&unique) {
+                    n += 1;
+                    unique = format!("{}-{}", id, n);
+                }
+                tag = set_attr(&tag, "id", &unique);
+                if anchor {
+                    tag = remove_attr(&tag, "name");  // Obsolete in EPUB 3
+                }
+                seen.push(unique);
+            }
+
+            // Links to a place in this page
+            if let Some(href) = get_attr(&tag, "href") {
+                let target = base.join(&href).ok();
+                let same_page = matches!(&target, Some(url) if url.scheme() == page_url.scheme()
+                    && url.host_str() == page_url.host_str() && url.path() == page_url.path()
+                    && url.query() == page_url.query());
+                // As written in the href first, URL parsing percent-encodes non-ASCII ids
+                let fragment = href.split_once('#').map(|(_, f)| f.to_string())
+                    .into_iter()
+                    .chain(target.as_ref().and_then(|url| url.fragment()).map(String::from))
+                    .find(|fragment| ids.contains(fragment));
+                if href.starts_with('#') || same_page {
+                    tag = match fragment {
+                        Some(fragment) => set_attr(&tag, "href", &format!("#{}", fragment)),
+                        _ => remove_attr(&tag, "href"),  // Nowhere to go in the book
+                    };
+                }
+            }
+
+            fixed.push_str(&content[pos..start]);
+            fixed.push_str(&tag);
+            pos = end;
+        }
+        fixed.push_str(&content[pos..]);
+
+        self.content = Some(fixed);
+    }
+
+    /// Replace links with numbered endnotes listed at the end of the article, for devices
+    /// where following links is awkward. Notes show the link resolved against `base`, so
+    /// relative ones still lead somewhere; in-page anchors are kept.
+    pub fn links_to_endnotes(&mut self, base: &Url) {
+        let content = match &self.content {
+            Some(content) => content,
+            None => return,
+        };
+
+        let mut kept = String::with_capacity(content.len());
+        let mut notes = Vec::new();
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find("<a ") {
+            let open_end = match rest[start..].find('>') {
+                Some(i) => start + i + 1,
+                None => break,
+            };
+            let close = match rest[open_end..].find("</a>") {
+                Some(i) => open_end + i,
+                None => break,
+            };
+            let target = Soup::new(&rest[start..open_end]).tag("a").find()
+                .and_then(|a| a.get("href"))
+                .filter(|href| !href.is_empty() && !href.starts_with('#'))
+                .and_then(|href| base.join(&href).ok());
+
+            kept.push_str(&rest[..start]);
+            match target {
+                Some(target) => {
+                    notes.push(target);
+                    kept.push_str(&rest[open_end..close]);
+                    kept.push_str(&format!("<sup>[{}]</sup>", notes.len()));
+                },
+                None => kept.push_str(&rest[start..close + 4]),
+            }
+            rest = &rest[close + 4..];
+        }
+        kept.push_str(rest);
+
+        if !notes.is_empty() {
+            kept.push_str(&format!("\n<h2>{}</h2>\n<ol>\n", tr("notes")));
+            for target in &notes {
+                kept.push_str(&format!("<li>{}</li>\n", escape_xml(target.as_str())));
+            }
+            kept.push_str("</ol>\n");
+        }
+
+        self.content = Some(kept);
+    }
+
+    /// Assemble the EPUB in memory from the article and its images
+    pub fn build_epub(&self, images: Vec<EpubImage>, css: &str) -> Result<Vec<u8>> {
+        // Create a new EpubBuilder using the zip library
+        let mut epub: Vec<u8> = vec!();
+
+        let epub_title = self.title.clone().unwrap_or_default();
+        let epub_author = self.byline.clone().unwrap_or_default();
+        let epub_content = to_xhtml(self.content.as_deref().unwrap_or_default());
+
+        let mut builder = EpubBuilder::new(ZipLibrary::new()?)?;
+        builder.metadata("author", epub_author)?;
+        builder.metadata("title", epub_title.clone())?;
+        builder.metadata("toc_name", tr("table-of-contents"))?;
+        if let Some(description) = &self.description {
+            builder.metadata("description", description.clone())?;
+        }
+        if let Some(lang) = &self.lang {
+            builder.metadata("lang", lang.clone())?;
+        }
+        builder.stylesheet(css.as_bytes())?;
+        for tag in &self.tags {
+            builder.metadata("subject", tag.clone())?;
+        }
+
+        for img in images {
+            builder.add_resource(img.filename, img.data, img.mime)?;
+        };
+
+        // Add title page
+        let mut title_body = format!("<h1>{}</h1>\n<p>{}</p>", escape_xml(&epub_title), escape_xml(self.byline()));
+        if !self.date().is_empty() {
+            title_body.push_str(&format!("\n<p>{}</p>", escape_xml(self.date())));
+        }
+        if let Some(source) = &self.source {
+            title_body.push_str(&format!(
+                "\n<p>{}: <a href=\"{1}\">{1}</a></p>", tr("source"), escape_xml(source)));
+        }
+        let title_page = xhtml_page(&epub_title, &title_body);
+        builder.add_content(EpubContent::new("title.xhtml", title_page.as_bytes())
+                     .title(epub_title.clone())
+                     .reftype(ReferenceType::TitlePage))?;
+
+        // Add Chapter
+        let article_page = xhtml_page(&epub_title, &epub_content);
+        builder.add_content(EpubContent::new("article.xhtml", article_page.as_bytes()))?;
+
+        builder.generate(&mut epub)?;
+
+        Ok(epub)
+    }
+}
 
-        // Check target URL validity
-        match target_url {
-            Ok(url) => { println!("{}", url) },
+/// Article image, downloaded in the temp dir
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ArticleImage {
+    pub url: Url,
+    pub aliases: Vec<Url>,  // Other URLs of the same image (thumbnail and full view)
+    pub path: PathBuf,
+    pub included: bool,  // Excluded images are left out of the book
+    pub thumbnail: Option<PathBuf>,  // Preview for the image picker, none if it can't be decoded
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ArticleImage {
+    /// All the URLs the article refers to this image with
+    pub fn urls(&self) -> impl Iterator<Item = &Url> {
+        std::iter::once(&self.url).chain(&self.aliases)
+    }
+}
+
+/// Article extracted from a page and waiting to be built, its files live in a temp dir
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Extraction {
+    pub article: Article,
+    pub images: Vec<ArticleImage>,
+    pub filename: String,  // Output EPUB filename
+    pub delivery: String,  // One of `delivery::TARGETS`
+    css: String,  // Stylesheet of the book
+    page_url: Url,
+    tmp_dir: TempDir,  // Removed when the extraction is dropped, whether it is built or not
+    report: ConversionReport,
+    processed: mpsc::Receiver<(PathBuf, Result<EpubImage>)>,  // Images prepared while downloading
+}
+
+// File system and process bound part of the pipeline (downloads, ReadabiliPy, output file)
+#[cfg(not(target_arch = "wasm32"))]
+impl Article {
+    /// Convert the page at `target` without any review, returning the title and the path of
+    /// the delivered book, `None` for invalid URLs
+    pub fn epub_from_url(
+        target: String,
+        cfg: HashMap<String, String>,
+        mode: HttpMode,
+        progress: &dyn Fn(Progress),
+    ) -> Result<Option<(String, PathBuf)>> {
+        let extraction = match Article::extract(target, &cfg, mode, progress)? {
+            Some(extraction) => extraction,
+            None => return Ok(None),
+        };
+        let title = extraction.article.title().to_string();
+        let path = extraction.build(&cfg, progress)?;
+        progress(Progress::Done);
+
+        Ok(Some((title, path)))
+    }
+
+    /// Fetch the page at `target`, extract its article and download its images
+    pub fn extract(
+        target: String,
+        cfg: &HashMap<String, String>,
+        mode: HttpMode,
+        progress: &dyn Fn(Progress),
+    ) -> Result<Option<Extraction>> {
+        // Parse target URL
+        let target_url = match Url::parse(&target) {
+            Ok(url) => { println!("{}", url); url },
             Err(e) => {
                 println!("Error {}, return.", e);
-                return Ok(())  // TODO: Implement Error InvalidURL
+                return Ok(None)  // TODO: Implement Error InvalidURL
             }
         };
 
-        // Make temp dir
-        let tmp_dir = Builder::new().prefix("kindle-pult_").tempdir()?;
-        // Persist the tempdir and return PathBuf
-        let tmp_dir_path = tmp_dir.into_path();
+        // Make temp dir, removed on drop if the extraction fails
+        let tmp_dir = Builder::new().prefix("kindle-pult_").tempdir_in(paths::cache_dir())?;
+        let tmp_dir_path = tmp_dir.path().to_path_buf();
 
         // Set up downloader for HTML files
-        let downloader = Downloader::new(tmp_dir_path.clone(), DLFileType::Text);
-        let target_url = Url::parse(&target);
-        let local_abs_path_string = downloader.download_from(target_url.unwrap());
+        progress(Progress::Fetch);
+        let downloader = Downloader::new(tmp_dir_path.clone(), DLFileType::Text, mode, HttpClient::from_cfg(cfg)?);
+        let (mut local_abs_path_string, mut final_url) = downloader.download_from(target_url.clone())?;
+        let mut report = ConversionReport { url: target_url.to_string(), ..Default::default() };
+
+        // Follow redirects done by the page itself (link shorteners, some publishers)
+        for _ in 0..MAX_SOFT_REDIRECTS {
+            let html = fs::read_to_string(&local_abs_path_string).unwrap_or_default();
+            match soft_redirect(&html, &final_url) {
+                Some(next) => {
+                    println!("Page redirects to {}", next);
+                    report.warnings.push(format!("Followed a redirect done by the page to {}", next));
+                    let (path, url) = downloader.download_from(next)?;
+                    local_abs_path_string = path;
+                    final_url = url;
+                },
+                None => break,
+            }
+        }
+
+        // Relative links are resolved against the page after redirects, or its <base href>.
+        // The page URL itself stays the source of the article.
+        let page_url = match fs::read_to_string(&local_abs_path_string) {
+            Ok(html) => page_base(&html, &final_url),
+            Err(_) => final_url.clone(),
+        };
 
         // Purify HTML
-        let purifier = ReadabiliPyCmd::new(ReadabiliPyParser::Mozilla);  // Select parser
+        let parser = ReadabiliPyParser::from_name(cfg.get("parser").unwrap());
+        let purifier = ReadabiliPyCmd::new(parser);
 
         let outfile_path = tmp_dir_path.join("article.json");  // TODO: use fname
         let outfile_path_string = outfile_path.clone().into_os_string().into_string().unwrap();
 
         // Generate json file with ReadabiliPy
-        // TODO: print feedback to GUI
-        purifier.json_from_file(local_abs_path_string.unwrap(), outfile_path_string);
+        progress(Progress::Extract);
+        purifier.json_from_file(local_abs_path_string, outfile_path_string.clone());
+        HookCmd::run(cfg.get("hook_extracted").unwrap(), &outfile_path_string);
 
         // Read Json, deserialize and print Rust data structure.
-        // TODO: print article info to GUI
-        let json_file = fs::File::open(outfile_path).expect("file not found");
-        let article: Article = serde_json::from_reader(json_file).expect("error reading json");
-
-        // Get absolute image urls
-        let image_urls = match article.clone().content {
-            Some(content) => {
-                let mut urls = Vec::new();
-                let soup = Soup::new(&content);
-
-                for img in soup.tag("img").find_all() {
-                    let image_url = img.get("src").expect("Couldn't find `src` attribute");
-
-                    // Make sure URL is absolute and add it to urls vector;
-                    match Url::parse(&image_url) {
-                        Ok(url) => {
-                            urls.push(url);
-                        },  // Already absolute, send to vector
-                        Err(e) => {
-                            match e {
-                                ParseError::RelativeUrlWithoutBase => {
-                                    println!("Relative URL: {}", &image_url);
-                                    let target_url = Url::parse(&target);  // Second parsing
-                                    let absolute_url = target_url.unwrap().join(&image_url)
-                                        .expect("Can't make absolute URL of image");
-
-                                    println!("absolute URL: {}", &absolute_url);
-                                    urls.push(absolute_url);
-                                },  // Relative URL error
-                                _ => {
-                                    println!("errore: {}", e);
-                                    return Ok(())
-                                }  // Unknown error
-                            };  // match error
-                        }  // if error
-                    }  // match url parse
-                };
+        let mut article = Article::from_json(&fs::read_to_string(outfile_path)?)?;
+        report.page_url = final_url.to_string();
+        report.parser = cfg.get("parser").unwrap().to_string();
+        report.title = article.title().to_string();
+        report.stripped = article.sanitize();
+        article.fix_internal_links(&page_url, &final_url);
 
-                println!("Image URLS: {:?}", urls);
-                urls
-            },
-            None => {
-                Vec::new()
-            } // Empty vector
+        // Caps for pathological pages, 0 means no cap
+        let limit = |key: &str| cfg.get(key).and_then(|value| value.trim().parse::<usize>().ok()).unwrap_or(0);
+        let (max_content, max_images, max_resources) =
+            (limit("max_content_kb") * 1024, limit("max_images"), limit("max_resources_mb") * 1024 * 1024);
+        let data_images = article.remove_data_images(MAX_DATA_URI_BYTES);
+        if data_images > 0 {
+            report.warnings.push(format!("{} oversized inline images removed", data_images));
+        }
+        if max_content > 0 && article.truncate(max_content) {
+            article.add_notice(tr("notice-cut"));
+            report.warnings.push(format!("Content cut at {} KiB", max_content / 1024));
+        }
+        if article.title().is_empty() {
+            report.warnings.push("No title found".into());
+        }
+        if final_url.scheme() != "file" {
+            article.set_source(final_url.to_string());
+        }
+        let filename = match slugify(article.title()).as_str() {
+            "" => "book.epub".to_string(),
+            slug => format!("{}.epub", slug),
         };
 
-        // Download images
+        // Download images, so they can be reviewed before building
         downloader.file_type.set(DLFileType::Image);
-        let mut local_abs_image_paths = Vec::new();
+        let img_opts = Arc::new(ImageOpts::from_cfg(cfg));
+        article.pick_srcset(img_opts.max_width);
+        article.fill_alt_texts();
+        // Checked against the served page, as <base href> can point anywhere
+        let (mut image_urls, unfetchable): (Vec<Url>, Vec<Url>) = article.image_urls(&page_url).into_iter()
+            .filter(|url| url.scheme() != "data")  // Small inline images stay as they are
+            .partition(|url| fetchable_image(url, &final_url));
+        if !unfetchable.is_empty() {
+            article.remove_images(&page_url, &unfetchable);
+            report.warnings.push(format!("{} images with local or unsupported URLs left out", unfetchable.len()));
+        }
+        if matches!(cfg.get("skip_images").map(String::as_str), Some("true")) {
+            article.remove_images(&page_url, &image_urls);  // Set when retrying a failed conversion
+            image_urls.clear();
+        }
+        report.images_found = image_urls.len();
+        if max_images > 0 && image_urls.len() > max_images {
+            article.remove_images(&page_url, &image_urls.split_off(max_images));
+            article.add_notice(tr("notice-too-many-images"));
+            report.warnings.push(format!("Only the first {} images kept", max_images));
+        }
+        let n_images = image_urls.len();
+        let mut resources_bytes = 0;
+
+        // Each URL is downloaded once, URLs of identical images share a single file
+        let mut images: Vec<ArticleImage> = Vec::with_capacity(n_images);
+        let (processed_sender, processed) = mpsc::channel();
+        let (thumbnail_sender, thumbnails) = mpsc::channel();
+        let mut dropped = Vec::new();
+        for (i, url) in image_urls.iter().cloned().enumerate() {
+            progress(Progress::Images(i, n_images));
+            if images.iter().any(|img| img.urls().any(|u| *u == url)) || dropped.contains(&url) {
+                report.images_duplicate += 1;
+                continue
+            }
+
+            // A missing image is left out rather than failing the whole article
+            let downloaded = match downloader.download_from(url.clone()) {
+                Err(Error(ErrorKind::Forbidden(_), _)) | Err(Error(ErrorKind::NotFound(_), _))
+                | Err(Error(ErrorKind::ServerError(..), _)) | Err(Error(ErrorKind::HttpStatus(..), _)) => {
+                    println!("Leaving out image '{}'", url);
+                    report.images_failed += 1;
+                    report.warnings.push(format!("Image {} could not be downloaded", url));
+                    dropped.push(url);
+                    continue
+                },
+                result => result?.0,
+            };
+            let path = content_addressed(Path::new(&downloaded))?;
+            report.images_downloaded += 1;
+            if let Some(img) = images.iter_mut().find(|img| img.path == path) {
+                // Same content as a kept image, sharing its file costs nothing
+                report.images_duplicate += 1;
+                img.aliases.push(url);
+                continue
+            }
+            if img_opts.is_decorative(&path) {
+                fs::remove_file(&path)?;
+                report.images_decorative += 1;
+                dropped.push(url);
+                continue
+            }
+
+            let bytes = fs::metadata(&path)?.len() as usize;
+            if max_resources > 0 && resources_bytes + bytes > max_resources {
+                // Over the cap, this image and the following ones not kept yet are left out.
+                // Its file is no kept image's, as those were matched above.
+                fs::remove_file(&path)?;
+                report.warnings.push(format!("Images past {} MiB left out", max_resources / 1024 / 1024));
+                article.add_notice(tr("notice-images-too-big"));
+                dropped.push(url);
+                dropped.extend(image_urls[i + 1..].iter()
+                    .filter(|url| !images.iter().any(|img| img.urls().any(|u| u == *url)))
+                    .cloned());
+                break
+            }
+            resources_bytes += bytes;
 
-        for url in image_urls {
-            let local_abs_path_string = downloader.download_from(url);
-            local_abs_image_paths.push(local_abs_path_string);
+            // CPU-bound work runs on the rayon pool while the next image downloads: the
+            // thumbnail for the preview first, then the transforms for the book
+            let (sender, img_opts, img_path) = (processed_sender.clone(), Arc::clone(&img_opts), path.clone());
+            let thumbnail_sender = thumbnail_sender.clone();
+            rayon::spawn(move || {
+                let _ = thumbnail_sender.send((img_path.clone(), thumbnail(&img_path, THUMBNAIL_SIZE).ok()));
+                let image = img_opts.process(&img_path);
+                let _ = sender.send((img_path, image));
+            });
+            images.push(ArticleImage { url, aliases: Vec::new(), path, included: true, thumbnail: None });
         }
+        drop(thumbnail_sender);
+        for (path, thumb) in thumbnails {
+            if let Some(img) = images.iter_mut().find(|img| img.path == path) {
+                img.thumbnail = thumb;
+            }
+        }
+        progress(Progress::Images(n_images, n_images));
+        article.remove_images(&page_url, &dropped);  // Missing images, tracking pixels, spacers and icons
 
-        // Build epub
-        // Create a new EpubBuilder using the zip library
-        let mut epub: Vec<u8> = vec!();
-        let mut epub_dest = fs::File::create("book.epub")?;  // TODO: use sluggified title
+        let delivery = cfg.get("delivery").unwrap().to_string();
+        // Options set for this article only
+        if let Some(date) = cfg.get("snapshot_date") {
+            article.set_date(date.clone());
+        }
+        if let Some(description) = cfg.get("description") {
+            article.set_description(description.clone());
+        }
+        if let Some(lang) = cfg.get("lang") {
+            article.set_lang(lang.clone());
+        }
+        if matches!(cfg.get("endnotes").map(String::as_str), Some("true")) {
+            article.links_to_endnotes(&page_url);
+        }
 
-        let epub_title = article.title.unwrap();
-        let epub_author = article.byline.unwrap();
-        let epub_content = article.content.unwrap();
+        let mut css = theme_css(cfg.get("css_theme").unwrap()).to_string();
+        if let Some(custom_css) = cfg.get("custom_css") {
+            css.push_str(&fs::read_to_string(custom_css)?);
+        }
 
-        let mut builder = EpubBuilder::new(ZipLibrary::new()?)?;
-        builder.metadata("author", epub_author)?;
-        builder.metadata("title", epub_title.clone())?;
+        Ok(Some(Extraction { article, images, filename, delivery, css, page_url, tmp_dir, report, processed }))
+    }
+}
 
-        for img in local_abs_image_paths {
-            // Image string path
-            let img = img.unwrap();
-            // Get filename and extenstion
-            let img_path = Path::new(&img);
-            let filename = img_path.file_name().unwrap();
-            let ext = img_path.extension().unwrap().to_str().unwrap();
-            let ext = format!("image/{}", ext);
-            // Open image as DynamicImage
-            let img_decoded = ImageReader::open(&img)?.decode()?;
+#[cfg(not(target_arch = "wasm32"))]
+impl Extraction {
+    /// Build the EPUB from the included images, save it in the configured format and
+    /// deliver it, returning where it ended up
+    pub fn build(self, cfg: &HashMap<String, String>, progress: &dyn Fn(Progress)) -> Result<PathBuf> {
+        let Extraction {
+            mut article, images, filename, delivery, css, page_url, tmp_dir, mut report, processed,
+        } = self;
 
-            // Image optimization (using Image or Photon?)
+        let (included, excluded): (Vec<_>, Vec<_>) = images.into_iter().partition(|img| img.included);
+        report.images_excluded = excluded.len();
+        report.images_embedded = included.len();
+        article.remove_images(&page_url, &excluded.iter().flat_map(|img| img.urls().cloned()).collect::<Vec<_>>());
 
-            builder.add_resource(filename, img_decoded.as_bytes(), ext)?;
-        };
+        // Wait for the transforms started while downloading, in article order. Images whose
+        // transform never reported back are prepared here.
+        let mut processed = processed.into_iter().collect::<HashMap<_, _>>();
+        let img_opts = ImageOpts::from_cfg(cfg);
+        let images = included.iter()
+            .map(|img| processed.remove(&img.path).unwrap_or_else(|| img_opts.process(&img.path)))
+            .collect::<Result<Vec<_>>>()?;
+        let names = included.into_iter().zip(&images)
+            .flat_map(|(img, epub_img)| img.urls().map(|url| (url.clone(), epub_img.filename.clone())).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        article.relink_images(&page_url, &names);
 
-        // Add title page
-        builder.add_content(EpubContent::new("title.xhtml", epub_title.clone().as_bytes())
-                     .title(epub_title)
-                     .reftype(ReferenceType::TitlePage))?;
+        // Build epub
+        progress(Progress::Build);
+        let epub = article.build_epub(images, &css)?;
 
-        // Add Chapter
-        builder.add_content(EpubContent::new("article.xhtml", epub_content.as_bytes()))?;
+        progress(Progress::Deliver);
+        if !is_epub(&epub) {
+            bail!("The generated EPUB is malformed");
+        }
+        let epub_path = paths::out_dir(cfg)?.join(&filename);
+        write_atomic(&epub_path, &epub)?;
+        HookCmd::run(cfg.get("hook_epub").unwrap(), &epub_path.to_string_lossy());
 
-        builder.generate(&mut epub)?;
+        // Delete the temporary directory ourselves, it's dropped with it on errors
+        tmp_dir.close()?;
+
+        // Other formats are converted from the EPUB, which is then dropped
+        let out_format = cfg.get("out_format").map(String::as_str).unwrap_or("epub");
+        let out_path = if out_format == "epub" {
+            epub_path
+        } else {
+            let out_path = epub_path.with_extension(out_format);
+            let part = partial_file(&out_path)?;
+            CalibreCmd::convert_to(&epub_path.to_string_lossy(), &part.path().to_string_lossy());
+            // Calibre writes by path, the size is read from there rather than from our handle
+            if fs::metadata(part.path())?.len() == 0 {
+                bail!("Calibre could not convert the book to {}", out_format);
+            }
+            part.persist(&out_path).map_err(|e| e.error)?;
+            fs::remove_file(&epub_path)?;
+            out_path
+        };
+
+        report.book_bytes = fs::metadata(&out_path)?.len();
+        let delivered = deliver(&out_path, &delivery, cfg)?;
+
+        if let Some(format) = ConversionReport::format(cfg.get("report").map(String::as_str).unwrap_or_default()) {
+            report.book = delivered.clone();
+            let report_path = report.save(&out_path, format)?;
+            println!("Conversion report saved in {:?}", report_path);
+        }
 
-        io::copy(&mut &epub[..], &mut epub_dest)
-            .expect("Failed to copy epub file to destination");
+        Ok(delivered)
+    }
 
-        // Delete the temporary directory ourselves.
-        fs::remove_dir_all(tmp_dir_path)?;
+    /// The article as the built-in preview shows it, images still pointing to their source
+    pub fn preview(&self) -> Vec<PreviewBlock> {
+        self.article.preview_blocks(&self.page_url)
+    }
 
+    /// Drop the extracted article without building it
+    pub fn discard(self) -> Result<()> {
+        self.tmp_dir.close()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epub_filename_is_safe_and_has_the_extension() {
+        assert_eq!(epub_filename("My book").as_deref(), Some("My_book.epub"));
+        assert_eq!(epub_filename("../notes/a.MOBI").as_deref(), Some("_notes_a.epub"));
+        assert_eq!(epub_filename("report.v2").as_deref(), Some("report.v2.epub"));
+        assert_eq!(epub_filename(" / "), None);
+    }
+
+    #[test]
+    fn soft_redirect_follows_refresh_and_scripts() {
+        let url = Url::parse("https://example.com/old").unwrap();
+        let refresh = r#"<html><head><meta http-equiv="Refresh" content="0; URL='/new'"></head><body>Moved</body></html>"#;
+        assert_eq!(soft_redirect(refresh, &url).map(String::from), Some("https://example.com/new".into()));
+        let script = r#"<html><body><script>window.location.href = "https://example.org/a";</script></body></html>"#;
+        assert_eq!(soft_redirect(script, &url).map(String::from), Some("https://example.org/a".into()));
+    }
+
+    #[test]
+    fn soft_redirect_leaves_articles_and_slow_refreshes_alone() {
+        let url = Url::parse("https://example.com/old").unwrap();
+        let article = format!(r#"<html><head><meta http-equiv="refresh" content="0; url=/new"></head><body><p>{}</p></body></html>"#, "text ".repeat(200));
+        assert_eq!(soft_redirect(&article, &url), None);
+        let slow = r#"<html><head><meta http-equiv="refresh" content="300; url=/new"></head><body></body></html>"#;
+        assert_eq!(soft_redirect(slow, &url), None);
+        let itself = r#"<html><head><meta http-equiv="refresh" content="0; url=/old"></head><body></body></html>"#;
+        assert_eq!(soft_redirect(itself, &url), None);
+        let script = r#"<html><body><script>location.href = "javascript:void(0)";</script></body></html>"#;
+        assert_eq!(soft_redirect(script, &url), None);
+    }
+
+    #[test]
+    fn to_xhtml_closes_tags_and_escapes_text() {
+        assert_eq!(to_xhtml("<p>Fish & chips<br><img src=a.jpg alt='x > y'>"), r#"<p>Fish &amp; chips<br /><img src="a.jpg" alt="x &gt; y" /></p>"#);
+        assert_eq!(to_xhtml("<ul><li>one<li>two</ul>"), "<ul><li>one</li><li>two</li></ul>");
+    }
+
+    #[test]
+    fn to_xhtml_removes_executable_content() {
+        assert_eq!(to_xhtml(r#"<p onclick="steal()">Hi<script>steal()</script></p>"#), "<p>Hi</p>");
+        assert_eq!(to_xhtml(r#"<a href="javascript:steal()">link</a>"#), "<a>link</a>");
+        assert_eq!(to_xhtml(r#"<form><input name="q"><button>Go</button></form>"#), "Go");
+        assert_eq!(
+            to_xhtml(r#"<iframe src="https://video.example/1"></iframe>"#),
+            r#"<p><a href="https://video.example/1">https://video.example/1</a></p>"#
+        );
+        assert_eq!(
+            to_xhtml(r#"<figure><img data-src="full.jpg"><noscript><img src="full.jpg"></noscript></figure>"#),
+            r#"<figure><img data-src="full.jpg" /><img src="full.jpg" /></figure>"#
+        );
+    }
+
+    /// Article with `content` and nothing else
+    fn article(content: &str) -> Article {
+        let mut article = Article::from_json("{}").unwrap();
+        article.content = Some(content.to_string());
+        article
+    }
+
+    #[test]
+    fn internal_links_point_to_the_anchors_in_the_book() {
+        let page = Url::parse("https://example.com/post").unwrap();
+        let mut article = article(concat!(
+            r##"<a name="top"></a><h2 id="s">One</h2><h2 id="s">Two</h2>"##,
+            r##"<a href="#s">1</a><a href="https://example.com/post#top">2</a><a href="#gone">3</a>"##,
+            r##"<a href="https://example.com/other#s">4</a>"##,
+        ));
+        article.fix_internal_links(&page, &page);
+        assert_eq!(article.content.unwrap(), concat!(
+            r##"<a id="top"></a><h2 id="s">One</h2><h2 id="s-2">Two</h2>"##,
+            r##"<a href="#s">1</a><a href="#top">2</a><a>3</a>"##,
+            r##"<a href="https://example.com/other#s">4</a>"##,
+        ));
+    }
+
+    #[test]
+    fn internal_links_resolve_against_the_base() {
+        let base = Url::parse("https://example.com/articles/").unwrap();
+        let page = Url::parse("https://example.com/articles/post").unwrap();
+        let mut article = article(r##"<h2 id="s">One</h2><a href="post#s">1</a><a href="other#s">2</a><a href="/articles/#s">3</a>"##);
+        article.fix_internal_links(&base, &page);
+        assert_eq!(
+            article.content.unwrap(),
+            r##"<h2 id="s">One</h2><a href="#s">1</a><a href="other#s">2</a><a href="/articles/#s">3</a>"##
+        );
+    }
+
+    #[test]
+    fn endnotes_hold_absolute_links() {
+        let base = Url::parse("https://example.com/blog/post").unwrap();
+        let mut article = article(r##"<p><a href="../about">me</a>, <a href="#s">below</a> and <a href="https://example.org/">there</a></p>"##);
+        article.links_to_endnotes(&base);
+        assert_eq!(article.content.unwrap(), format!(
+            "{}\n<h2>{}</h2>\n<ol>\n<li>https://example.com/about</li>\n<li>https://example.org/</li>\n</ol>\n",
+            r##"<p>me<sup>[1]</sup>, <a href="#s">below</a> and there<sup>[2]</sup></p>"##, tr("notes"),
+        ));
+    }
+
+    #[test]
+    fn web_pages_cannot_embed_local_files() {
+        let remote = Url::parse("https://example.com/post").unwrap();
+        let local = Url::parse("file:///home/user/page.html").unwrap();
+        let article = article(r#"<img src="file:///home/user/.ssh/id_rsa"><img src="/a.jpg"><img src="cid:part1">"#);
+        let urls = article.image_urls(&remote);
+        assert_eq!(urls.len(), 3);
+        let fetched: Vec<_> = urls.iter().filter(|url| fetchable_image(url, &remote)).map(Url::as_str).collect();
+        assert_eq!(fetched, ["https://example.com/a.jpg"]);
+        assert!(fetchable_image(&Url::parse("file:///home/user/a.jpg").unwrap(), &local));
+        assert!(!fetchable_image(&Url::parse("javascript:alert(1)").unwrap(), &local));
+    }
+
+    #[test]
+    fn preview_keeps_blocks_in_reading_order() {
+        let page = Url::parse("https://example.com/posts/1").unwrap();
+        let article = article(concat!(
+            r#"<h2>Title</h2><div><p>One <b>two</b>
+            three</p><img src="a.jpg"></div><script>x()</script>"#,
+            r#"<blockquote><p>Said</p><p>so</p></blockquote><ul><li>Item <img src="/b.png"></li></ul>"#,
+        ));
+        assert_eq!(article.preview_blocks(&page), [
+            PreviewBlock::Heading("Title".into()),
+            PreviewBlock::Paragraph("One two three".into()),
+            PreviewBlock::Image(Url::parse("https://example.com/posts/a.jpg").unwrap()),
+            PreviewBlock::Quote("Said\nso".into()),
+            PreviewBlock::Paragraph("Item".into()),
+            PreviewBlock::Image(Url::parse("https://example.com/b.png").unwrap()),
+        ]);
+    }
+
+    #[test]
+    fn srcset_picks_smallest_fitting_candidate() {
+        let srcset = "small.jpg 400w, medium.jpg 800w, large.jpg 1600w";
+        assert_eq!(srcset_candidate(srcset, 600).as_deref(), Some("medium.jpg"));
+        assert_eq!(srcset_candidate(srcset, 800).as_deref(), Some("medium.jpg"));
+        assert_eq!(srcset_candidate(srcset, 2000).as_deref(), Some("large.jpg"));
+    }
+
+    #[test]
+    fn srcset_ranks_densities_and_bare_urls() {
+        assert_eq!(srcset_candidate("a.jpg, b.jpg 2x", 600).as_deref(), Some("a.jpg"));
+        assert_eq!(srcset_candidate("a.jpg 400w, b.jpg 1600w", u32::MAX).as_deref(), Some("b.jpg"));
+        assert_eq!(srcset_candidate("a.jpg 2x,b.jpg 3x", 600).as_deref(), Some("a.jpg"));
+    }
+
+    #[test]
+    fn srcset_skips_malformed_descriptors() {
+        assert_eq!(srcset_candidate("a.jpg 100w, b.jpg NaNw", 50).as_deref(), Some("a.jpg"));
+        assert_eq!(srcset_candidate("a.jpg 100w, b.jpg infx", 500).as_deref(), Some("a.jpg"));
+        assert_eq!(srcset_candidate("a.jpg NaNx", 500), None);
+        assert_eq!(srcset_candidate("", 500), None);
+    }
+}